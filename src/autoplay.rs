@@ -0,0 +1,136 @@
+//! The game playing itself.
+//!
+//! A toggles autoplay and Shift+A switches to the next strategy. Moves are
+//! chosen on the async compute pool and sent as [`MoveRequest`]s, so they
+//! animate, score and end the game exactly like the player's.
+
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use bevy::tasks::{block_on, poll_once, AsyncComputeTaskPool, Task};
+use shadowmitia_2048::grid::{Direction, Grid};
+use shadowmitia_2048::strategy::{Strategy, StrategyKind};
+
+use crate::options::Options;
+use crate::{apply_move, AppState, MoveRequest};
+
+#[derive(Resource)]
+struct Autoplay {
+    on: bool,
+    kind: StrategyKind,
+    strategy: Arc<Mutex<Box<dyn Strategy>>>,
+    /// Moves per second.
+    rate: f32,
+    since_move: f32,
+    /// The board the last choice was asked for.
+    asked: Vec<u8>,
+    task: Option<Task<Option<Direction>>>,
+    /// A move waiting for its turn.
+    ready: Option<Direction>,
+}
+
+impl Autoplay {
+    fn new(kind: StrategyKind, rate: f32) -> Self {
+        Self {
+            on: false,
+            kind,
+            strategy: Arc::new(Mutex::new(kind.build())),
+            rate,
+            since_move: 0.0,
+            asked: Vec::new(),
+            task: None,
+            ready: None,
+        }
+    }
+
+    /// Forget the move being chosen, so the next one is asked afresh.
+    fn reset(&mut self) {
+        self.asked.clear();
+        self.task = None;
+        self.ready = None;
+    }
+}
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Startup, setup_autoplay).add_systems(
+        Update,
+        (autoplay_keys, drive_autoplay)
+            .chain()
+            .before(apply_move)
+            .run_if(in_state(AppState::InGame)),
+    );
+}
+
+fn setup_autoplay(mut commands: Commands, options: Res<Options>) {
+    commands.insert_resource(Autoplay::new(options.strategy, options.autoplay_rate));
+}
+
+fn autoplay_keys(input: Res<ButtonInput<KeyCode>>, mut autoplay: ResMut<Autoplay>) {
+    if !input.just_pressed(KeyCode::KeyA) {
+        return;
+    }
+
+    if input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        let kind = autoplay.kind.next();
+        *autoplay = Autoplay {
+            on: autoplay.on,
+            ..Autoplay::new(kind, autoplay.rate)
+        };
+        info!("autoplay strategy: {kind}");
+    } else {
+        autoplay.on = !autoplay.on;
+        autoplay.reset();
+        info!(
+            "autoplay {} ({}, {} moves a second)",
+            if autoplay.on { "on" } else { "off" },
+            autoplay.kind,
+            autoplay.rate
+        );
+    }
+}
+
+/// Ask the strategy for a move whenever the board changes, and play it
+/// once the last move is old enough.
+fn drive_autoplay(
+    time: Res<Time>,
+    grid: Res<Grid>,
+    mut autoplay: ResMut<Autoplay>,
+    mut requests: EventWriter<MoveRequest>,
+) {
+    let autoplay = &mut *autoplay;
+    if !autoplay.on {
+        return;
+    }
+    autoplay.since_move += time.delta_seconds();
+
+    if autoplay.asked != grid.cells {
+        autoplay.asked.clone_from(&grid.cells);
+        autoplay.ready = None;
+        let strategy = autoplay.strategy.clone();
+        let board = grid.clone();
+        autoplay.task =
+            Some(AsyncComputeTaskPool::get().spawn(async move {
+                strategy.lock().expect("a strategy panicked").choose(&board)
+            }));
+    }
+
+    if let Some(task) = &mut autoplay.task {
+        if let Some(choice) = block_on(poll_once(task)) {
+            autoplay.task = None;
+            autoplay.ready = choice;
+            if choice.is_none() {
+                autoplay.on = false;
+                info!("autoplay off: no move left");
+            }
+        }
+    }
+
+    if autoplay.since_move >= 1.0 / autoplay.rate {
+        if let Some(direction) = autoplay.ready.take() {
+            requests.send(MoveRequest(direction));
+            autoplay.since_move = 0.0;
+            // Ask again even if another request gets played instead.
+            autoplay.asked.clear();
+        }
+    }
+}
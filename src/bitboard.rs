@@ -0,0 +1,257 @@
+//! Packed 4x4 board for search code.
+//!
+//! Each cell is stored as its 4-bit rank, exactly like `Grid::cells`, so a
+//! whole board fits in a `u64`. Row `j` occupies bits
+//! `16 * j..16 * j + 16` and cell `(i, j)` is nibble `i` of that row, matching
+//! `Grid::index_2d`. Slides are lookups in tables computed once for all 65536
+//! possible rows.
+//!
+//! Ranks stop at 15 (32768): two 32768 tiles do not merge.
+
+use std::sync::OnceLock;
+
+use crate::grid::{Direction, Grid};
+
+const SIDE: usize = 4;
+const ROWS: usize = 1 << 16;
+const MAX_RANK: u16 = 15;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bitboard(pub u64);
+
+struct Tables {
+    left: Vec<u16>,
+    right: Vec<u16>,
+    left_score: Vec<u32>,
+    right_score: Vec<u32>,
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut tables = Tables {
+            left: vec![0; ROWS],
+            right: vec![0; ROWS],
+            left_score: vec![0; ROWS],
+            right_score: vec![0; ROWS],
+        };
+        for row in 0..ROWS {
+            let row = row as u16;
+            let (left, score) = slide_row_left(row);
+            tables.left[row as usize] = left;
+            tables.left_score[row as usize] = score;
+
+            let (right, score) = slide_row_left(reverse_row(row));
+            tables.right[row as usize] = reverse_row(right);
+            tables.right_score[row as usize] = score;
+        }
+        tables
+    })
+}
+
+/// Slide a packed row towards nibble 0.
+fn slide_row_left(row: u16) -> (u16, u32) {
+    let mut line = [0u16; SIDE];
+    let mut next = 0;
+    let mut can_merge = false;
+    let mut score = 0;
+    for k in 0..SIDE {
+        let rank = (row >> (4 * k)) & 0xf;
+        if rank == 0 {
+            continue;
+        }
+        if can_merge && line[next - 1] == rank && rank < MAX_RANK {
+            line[next - 1] += 1;
+            score += 1 << line[next - 1];
+            can_merge = false;
+        } else {
+            line[next] = rank;
+            next += 1;
+            can_merge = true;
+        }
+    }
+    let packed = line
+        .iter()
+        .enumerate()
+        .fold(0, |acc, (k, &e)| acc | (e << (4 * k)));
+    (packed, score)
+}
+
+fn reverse_row(row: u16) -> u16 {
+    (row >> 12) | ((row >> 4) & 0x00f0) | ((row << 4) & 0x0f00) | (row << 12)
+}
+
+impl Bitboard {
+    /// Pack a classic board. `None` if the grid is not 4x4 or holds a tile
+    /// above 32768.
+    #[must_use]
+    pub fn from_grid(grid: &Grid) -> Option<Self> {
+        if grid.width != SIDE || grid.height != SIDE {
+            return None;
+        }
+        let mut board = 0u64;
+        for (index, &rank) in grid.cells.iter().enumerate() {
+            if rank as u16 > MAX_RANK {
+                return None;
+            }
+            board |= (rank as u64) << (4 * index);
+        }
+        Some(Self(board))
+    }
+
+    /// Unpack into a 4x4 grid with the default spawn rules.
+    #[must_use]
+    pub fn to_grid(self) -> Grid {
+        let mut grid = Grid::with_size(SIDE, SIDE);
+        for (index, cell) in grid.cells.iter_mut().enumerate() {
+            *cell = self.rank(index);
+        }
+        grid
+    }
+
+    /// Rank stored at `index` (as given by `Grid::index_2d`).
+    #[must_use]
+    pub fn rank(self, index: usize) -> u8 {
+        ((self.0 >> (4 * index)) & 0xf) as u8
+    }
+
+    #[must_use]
+    pub fn empty_cells(self) -> usize {
+        (0..SIDE * SIDE).filter(|&i| self.rank(i) == 0).count()
+    }
+
+    #[must_use]
+    pub fn max_rank(self) -> u8 {
+        (0..SIDE * SIDE).map(|i| self.rank(i)).max().unwrap_or(0)
+    }
+
+    /// Swap rows and columns.
+    #[must_use]
+    pub fn transpose(self) -> Self {
+        let x = self.0;
+        let a1 = x & 0xF0F0_0F0F_F0F0_0F0F;
+        let a2 = x & 0x0000_F0F0_0000_F0F0;
+        let a3 = x & 0x0F0F_0000_0F0F_0000;
+        let a = a1 | (a2 << 12) | (a3 >> 12);
+        let b1 = a & 0xFF00_FF00_00FF_00FF;
+        let b2 = a & 0x00FF_00FF_0000_0000;
+        let b3 = a & 0x0000_0000_FF00_FF00;
+        Self(b1 | (b2 >> 24) | (b3 << 24))
+    }
+
+    /// Same rules as [`Grid::slide`]; returns the new board and the score
+    /// gained. The board is unchanged if nothing could move.
+    #[must_use]
+    pub fn slide(self, direction: Direction) -> (Self, u64) {
+        let tables = tables();
+        let (board, (rows, scores)) = match direction {
+            Direction::Left => (self, (&tables.left, &tables.left_score)),
+            Direction::Right => (self, (&tables.right, &tables.right_score)),
+            // Columns become rows; down goes towards row 0 like left.
+            Direction::Down => (self.transpose(), (&tables.left, &tables.left_score)),
+            Direction::Up => (self.transpose(), (&tables.right, &tables.right_score)),
+        };
+
+        let mut result = 0u64;
+        let mut score = 0u64;
+        for j in 0..SIDE {
+            let row = ((board.0 >> (16 * j)) & 0xffff) as usize;
+            result |= (rows[row] as u64) << (16 * j);
+            score += scores[row] as u64;
+        }
+
+        let result = Self(result);
+        match direction {
+            Direction::Left | Direction::Right => (result, score),
+            Direction::Up | Direction::Down => (result.transpose(), score),
+        }
+    }
+
+    #[must_use]
+    pub fn has_legal_move(self) -> bool {
+        Direction::ALL
+            .into_iter()
+            .any(|direction| self.slide(direction).0 != self)
+    }
+}
+
+#[cfg(test)]
+mod bitboard_tests {
+    use rand::prelude::*;
+
+    use super::*;
+    use crate::rng::GameRng;
+
+    #[test]
+    fn round_trips_through_grid() {
+        let mut grid = Grid::new();
+        #[rustfmt::skip]
+        let test = [0, 2, 4, 8,
+                    16, 32, 64, 128,
+                    256, 512, 1024, 2048,
+                    4096, 8192, 16384, 32768];
+        grid.set_values(&test);
+
+        let board = Bitboard::from_grid(&grid).unwrap();
+
+        assert_eq!(board.to_grid().values(), test);
+        assert_eq!(board.rank(Grid::index_2d(3, 0, 4, 4)), 3);
+        assert_eq!(board.max_rank(), 15);
+        assert_eq!(board.empty_cells(), 1);
+    }
+
+    #[test]
+    fn rejects_unrepresentable_grids() {
+        assert_eq!(Bitboard::from_grid(&Grid::with_size(5, 5)), None);
+
+        let mut grid = Grid::new();
+        grid.cells[0] = 16;
+        assert_eq!(Bitboard::from_grid(&grid), None);
+    }
+
+    #[test]
+    fn transpose_swaps_rows_and_columns() {
+        let mut grid = Grid::new();
+        grid.cells = (0..16).collect();
+        let transposed = Bitboard::from_grid(&grid).unwrap().transpose();
+
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_eq!(
+                    transposed.rank(Grid::index_2d(i, j, 4, 4)),
+                    Bitboard::from_grid(&grid)
+                        .unwrap()
+                        .rank(Grid::index_2d(j, i, 4, 4))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn slide_matches_grid() {
+        let mut rng = GameRng::from_seed(11);
+        for _ in 0..5000 {
+            let mut grid = Grid::new();
+            // Keep ranks below 15 so every merge is representable.
+            for cell in grid.cells.iter_mut() {
+                *cell = match rng.gen_range(0..15) {
+                    0 => 0,
+                    rank if rng.gen_bool(0.5) => rank,
+                    _ => 1,
+                };
+            }
+            let board = Bitboard::from_grid(&grid).unwrap();
+
+            for direction in Direction::ALL {
+                let mut expected = grid.clone();
+                let outcome = expected.slide(direction);
+                let (slid, score) = board.slide(direction);
+
+                assert_eq!(slid.to_grid().cells, expected.cells, "{direction:?}");
+                assert_eq!(score, outcome.score, "{direction:?}");
+                assert_eq!(slid != board, outcome.changed, "{direction:?}");
+            }
+            assert_eq!(board.has_legal_move(), grid.has_legal_move());
+        }
+    }
+}
@@ -1,9 +1,160 @@
 use bevy::prelude::*;
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Side length of the classic board.
+pub const DEFAULT_SIZE: usize = 4;
+
+/// Smallest and largest boards we agree to lay out.
+pub const MIN_SIZE: usize = 2;
+pub const MAX_SIZE: usize = 16;
+
+/// Value of a tile of the given rank: 0 for an empty cell, `2^rank`
+/// otherwise (saturating far beyond any reachable tile).
+#[must_use]
+pub fn rank_to_value(rank: u8) -> u64 {
+    match rank {
+        0 => 0,
+        rank => 1u64.checked_shl(rank as u32).unwrap_or(u64::MAX),
+    }
+}
+
+/// Rank of a tile value, `None` unless it is 0 or a power of two above 1.
+#[must_use]
+pub fn value_to_rank(value: u64) -> Option<u8> {
+    match value {
+        0 => Some(0),
+        1 => None,
+        v if v.is_power_of_two() => Some(v.trailing_zeros() as u8),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Left,
+        Direction::Right,
+        Direction::Up,
+        Direction::Down,
+    ];
+}
+
+/// A tile that changed cell during a slide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileSlide {
+    pub from: UVec2,
+    pub to: UVec2,
+}
+
+/// Two equal tiles that became one during a slide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileMerge {
+    /// Where the tile that stays on the board came from.
+    pub survivor: UVec2,
+    /// Where the tile that got absorbed came from.
+    pub absorbed: UVec2,
+    /// Cell holding the merged tile.
+    pub to: UVec2,
+    /// Value of the merged tile.
+    pub value: u64,
+}
+
+/// What a call to [`Grid::slide`] did to the board.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MoveOutcome {
+    /// Every tile that moved, merged or not, in board order.
+    pub slides: Vec<TileSlide>,
+    pub merges: Vec<TileMerge>,
+    /// Sum of the values of the tiles created by merges.
+    pub score: u64,
+    /// False when the slide left the board untouched.
+    pub changed: bool,
+}
 
-#[derive(Resource, Default, Copy, Clone)]
+/// How new tiles appear on the board.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpawnRules {
+    /// Tile values and their relative weights.
+    pub values: Vec<(u64, f32)>,
+    /// Tiles added after every move that changed the board.
+    pub per_move: usize,
+    /// Tiles placed on an empty board at the start of a game.
+    pub starting: usize,
+}
+
+impl Default for SpawnRules {
+    fn default() -> Self {
+        Self::normal()
+    }
+}
+
+impl SpawnRules {
+    /// Only 2s.
+    #[must_use]
+    pub fn easy() -> Self {
+        Self {
+            values: vec![(2, 1.0)],
+            per_move: 1,
+            starting: 2,
+        }
+    }
+
+    /// The classic rules: 90% 2s, 10% 4s.
+    #[must_use]
+    pub fn normal() -> Self {
+        Self {
+            values: vec![(2, 0.9), (4, 0.1)],
+            per_move: 1,
+            starting: 2,
+        }
+    }
+
+    /// More 4s, the odd 8 and a crowded start.
+    #[must_use]
+    pub fn hard() -> Self {
+        Self {
+            values: vec![(2, 0.7), (4, 0.25), (8, 0.05)],
+            per_move: 1,
+            starting: 3,
+        }
+    }
+
+    /// Pick a tile value according to the weights.
+    pub fn pick_value(&self, rng: &mut impl Rng) -> u64 {
+        let total = self.values.iter().map(|&(_, weight)| weight).sum::<f32>();
+        let mut roll = rng.gen::<f32>() * total;
+        for &(value, weight) in &self.values {
+            if roll < weight {
+                return value;
+            }
+            roll -= weight;
+        }
+        // Only reachable through rounding errors.
+        self.values.last().expect("spawn rules without values").0
+    }
+}
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
 pub struct Grid {
-    pub cells: [usize; 16],
+    pub width: usize,
+    pub height: usize,
+    /// Tile ranks: 0 for an empty cell, `n` for a tile worth `2^n`.
+    pub cells: Vec<u8>,
+    pub rules: SpawnRules,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self::with_size(DEFAULT_SIZE, DEFAULT_SIZE)
+    }
 }
 
 impl Grid {
@@ -12,212 +163,198 @@ impl Grid {
         default()
     }
 
+    /// Empty board `width` cells across and `height` cells tall.
     #[must_use]
-    pub fn default() -> Self {
-        Self { cells: [0; 16] }
+    pub fn with_size(width: usize, height: usize) -> Self {
+        assert!(
+            width >= 2 && height >= 2,
+            "a board needs at least 2 cells in each direction"
+        );
+        Self {
+            width,
+            height,
+            cells: vec![0; width * height],
+            rules: SpawnRules::default(),
+        }
     }
 
+    /// Board holding the given tile values, row by row from `(0, 0)`.
+    ///
+    /// Panics if `values` has the wrong length or holds something that is
+    /// not a tile value.
     #[must_use]
-    pub fn add_random_tile(&mut self) -> Option<UVec2> {
-        if !self.has_empty_cells() {
-            return None;
+    pub fn from_values(width: usize, height: usize, values: &[u64]) -> Self {
+        let mut grid = Self::with_size(width, height);
+        grid.set_values(values);
+        grid
+    }
+
+    /// Replace every cell with the given tile values.
+    pub fn set_values(&mut self, values: &[u64]) {
+        assert_eq!(values.len(), self.cells.len(), "wrong number of cells");
+        for (cell, &value) in self.cells.iter_mut().zip(values) {
+            *cell = value_to_rank(value).unwrap_or_else(|| panic!("{value} is not a tile value"));
         }
-        let mut rng = thread_rng();
+    }
 
-        let mut empty_cells = self
-            .cells
-            .iter()
-            .enumerate()
-            .filter_map(|(i, &c)| if c == 0 { Some(i) } else { None })
-            .collect::<Vec<usize>>();
-        empty_cells.shuffle(&mut rng);
+    /// Tile values of every cell, row by row from `(0, 0)`.
+    #[must_use]
+    pub fn values(&self) -> Vec<u64> {
+        self.cells.iter().map(|&rank| rank_to_value(rank)).collect()
+    }
 
-        let index = empty_cells[0];
+    #[must_use]
+    pub fn value(&self, index: usize) -> u64 {
+        rank_to_value(self.cells[index])
+    }
 
-        self.cells[index] = if rng.gen::<f32>() < 0.9 { 2 } else { 4 };
+    #[must_use]
+    pub fn value_at(&self, coord: UVec2) -> u64 {
+        self.value(Self::index_2d(
+            coord.x as usize,
+            coord.y as usize,
+            self.width,
+            self.height,
+        ))
+    }
 
-        Some(Grid::index_to_coord(index, 4, 4))
+    #[must_use]
+    pub fn rank_at(&self, coord: UVec2) -> u8 {
+        self.cells[Self::index_2d(coord.x as usize, coord.y as usize, self.width, self.height)]
     }
 
     #[must_use]
-    pub fn move_left(&mut self) -> (Vec<(UVec2, UVec2)>, usize) {
-        let mut moved = Vec::new();
-        let mut score = 0;
-        let mut has_merged = [false; 16];
-        for j in 0..4 {
-            for i in 1..4 {
-                let from = UVec2::new(i, j);
-                let prev = Self::index_2d(i as usize, j as usize, 4, 4);
-                if self.cells[prev] == 0 {
-                    continue;
-                }
-                let mut furthest = None;
-                // TODO: can be simplified
-                for k in 1..=i {
-                    let index = Self::index_2d((i - k) as usize, j as usize, 4, 4);
-                    if self.cells[index] == 0 || self.cells[index] == self.cells[prev] {
-                        furthest = Some(index);
-                    } else {
-                        break;
-                    }
-                }
-                if let Some(index) = furthest {
-                    if !has_merged[index] {
-                        if self.cells[index] == self.cells[prev] {
-                            self.cells[index] += self.cells[prev];
-                            score += self.cells[index];
-                            has_merged[index] = true;
-                        } else {
-                            self.cells[index] = self.cells[prev];
-                        }
-                        self.cells[prev] = 0;
-                        moved.push((from, UVec2::new((index % 4) as u32, (index / 4) as u32)));
-                    }
-                }
-            }
-        }
-        (moved, score)
+    pub fn with_rules(mut self, rules: SpawnRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Place the starting tiles of a new game.
+    pub fn start(&mut self, rng: &mut impl Rng) -> Vec<UVec2> {
+        (0..self.rules.starting)
+            .map_while(|_| self.add_random_tile(rng))
+            .collect()
     }
 
+    /// Add the tiles that follow a move, returning where they landed; fewer
+    /// than `rules.per_move` if the board filled up.
+    pub fn spawn_tiles(&mut self, rng: &mut impl Rng) -> Vec<UVec2> {
+        (0..self.rules.per_move)
+            .map_while(|_| self.add_random_tile(rng))
+            .collect()
+    }
+
+    /// Spawn a tile on a random empty cell, drawing every random choice
+    /// from `rng` so seeded generators give reproducible games.
     #[must_use]
-    pub fn move_right(&mut self) -> (Vec<(UVec2, UVec2)>, usize) {
-        let mut moved = Vec::new();
-        let mut score = 0;
-        let mut has_merged = [false; 16];
-        for j in 0..4 {
-            for i in (0..=2).rev() {
-                let from = UVec2::new(i, j);
-                let prev = Self::index_2d(i as usize, j as usize, 4, 4);
-                if self.cells[prev] == 0 {
-                    continue;
-                }
-                let mut furthest = None;
-                // TODO: can be simplified
-                for k in 1..=(3 - i) {
-                    let index = Self::index_2d((i + k) as usize, j as usize, 4, 4);
-                    if self.cells[index] == 0 || self.cells[index] == self.cells[prev] {
-                        furthest = Some(index);
-                    } else {
-                        break;
-                    }
-                }
-                if let Some(index) = furthest {
-                    if !has_merged[index] {
-                        if self.cells[index] == self.cells[prev] {
-                            self.cells[index] += self.cells[prev];
-                            score += self.cells[index];
-                            has_merged[index] = true;
-                        } else {
-                            self.cells[index] = self.cells[prev];
-                        }
-                        self.cells[prev] = 0;
-                        moved.push((from, UVec2::new((index % 4) as u32, (index / 4) as u32)));
-                    }
-                }
-            }
-        }
-        (moved, score)
+    pub fn add_random_tile(&mut self, rng: &mut impl Rng) -> Option<UVec2> {
+        let empty_cells = self
+            .cells
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &c)| if c == 0 { Some(i) } else { None })
+            .collect::<Vec<usize>>();
+
+        let &index = empty_cells.choose(rng)?;
+
+        let value = self.rules.pick_value(rng);
+        self.cells[index] = value_to_rank(value)
+            .unwrap_or_else(|| panic!("spawn rules hold {value}, not a tile value"));
+
+        Some(Grid::index_to_coord(index, self.width, self.height))
     }
 
+    /// Slide every tile towards `direction`, merging equal neighbours.
+    ///
+    /// Tiles are taken in order starting from the edge they slide towards; a
+    /// tile created by a merge cannot merge again during the same slide.
     #[must_use]
-    pub fn move_down(&mut self) -> (Vec<(UVec2, UVec2)>, usize) {
-        let mut moved = Vec::new();
-        let mut score = 0;
-        let mut has_merged = [false; 16];
-        for j in 1..4 {
-            for i in 0..4 {
-                let from = UVec2::new(i, j);
-                let prev = Self::index_2d(i as usize, j as usize, 4, 4);
-                if self.cells[prev] == 0 {
+    pub fn slide(&mut self, direction: Direction) -> MoveOutcome {
+        let mut outcome = MoveOutcome::default();
+        let coord = |index| Grid::index_to_coord(index, self.width, self.height);
+        for line in self.lines(direction) {
+            // First free slot in the line, and the slot and origin of the
+            // tile just before it if that tile may still absorb an equal one.
+            let mut next = 0;
+            let mut last = None;
+            for &from in &line {
+                let rank = self.cells[from];
+                if rank == 0 {
                     continue;
                 }
-                let mut furthest = None;
-                // TODO: can be simplified
-                for k in 1..=j {
-                    let index = Self::index_2d(i as usize, (j - k) as usize, 4, 4);
-                    if self.cells[index] == 0 || self.cells[index] == self.cells[prev] {
-                        furthest = Some(index);
-                    } else {
-                        break;
+                self.cells[from] = 0;
+
+                let to = match last {
+                    Some((slot, origin)) if self.cells[slot] == rank => {
+                        self.cells[slot] += 1;
+                        let value = rank_to_value(self.cells[slot]);
+                        outcome.score += value;
+                        outcome.merges.push(TileMerge {
+                            survivor: coord(origin),
+                            absorbed: coord(from),
+                            to: coord(slot),
+                            value,
+                        });
+                        last = None;
+                        slot
                     }
-                }
-                if let Some(index) = furthest {
-                    if !has_merged[index] {
-                        if self.cells[index] == self.cells[prev] {
-                            self.cells[index] += self.cells[prev];
-                            score += self.cells[index];
-                            has_merged[index] = true;
-                        } else {
-                            self.cells[index] = self.cells[prev];
-                        }
-                        self.cells[prev] = 0;
-                        moved.push((from, UVec2::new((index % 4) as u32, (index / 4) as u32)));
+                    _ => {
+                        let to = line[next];
+                        self.cells[to] = rank;
+                        next += 1;
+                        last = Some((to, from));
+                        to
                     }
+                };
+
+                if from != to {
+                    outcome.slides.push(TileSlide {
+                        from: coord(from),
+                        to: coord(to),
+                    });
                 }
             }
         }
-        (moved, score)
+        outcome.changed = !outcome.slides.is_empty();
+        outcome
     }
 
-    #[must_use]
-    pub fn move_up(&mut self) -> (Vec<(UVec2, UVec2)>, usize) {
-        let mut moved = Vec::new();
-        let mut score = 0;
-        let mut has_merged = [false; 16];
-        for j in (0..=2).rev() {
-            for i in 0..4 {
-                let from = UVec2::new(i, j);
-                let prev = Self::index_2d(i as usize, j as usize, 4, 4);
-                if self.cells[prev] == 0 {
-                    continue;
-                }
-                let mut furthest = None;
-                // TODO: can be simplified
-                for k in 1..=(3 - j) {
-                    let index = Self::index_2d(i as usize, (j + k) as usize, 4, 4);
-                    if self.cells[index] == 0 || self.cells[index] == self.cells[prev] {
-                        furthest = Some(index);
-                    } else {
-                        break;
-                    }
-                }
-                if let Some(index) = furthest {
-                    if !has_merged[index] {
-                        if self.cells[index] == self.cells[prev] {
-                            self.cells[index] += self.cells[prev];
-                            score += self.cells[index];
-                            has_merged[index] = true;
-                        } else {
-                            self.cells[index] = self.cells[prev];
-                        }
-                        self.cells[prev] = 0;
-                        moved.push((from, UVec2::new((index % 4) as u32, (index / 4) as u32)));
-                    }
-                }
-            }
+    /// Cell indices of every row (or column) along `direction`, each ordered
+    /// starting from the edge the tiles slide towards.
+    fn lines(&self, direction: Direction) -> Vec<Vec<usize>> {
+        let (w, h) = (self.width, self.height);
+        match direction {
+            Direction::Left => (0..h)
+                .map(|j| (0..w).map(|i| Self::index_2d(i, j, w, h)).collect())
+                .collect(),
+            Direction::Right => (0..h)
+                .map(|j| (0..w).rev().map(|i| Self::index_2d(i, j, w, h)).collect())
+                .collect(),
+            Direction::Down => (0..w)
+                .map(|i| (0..h).map(|j| Self::index_2d(i, j, w, h)).collect())
+                .collect(),
+            Direction::Up => (0..w)
+                .map(|i| (0..h).rev().map(|j| Self::index_2d(i, j, w, h)).collect())
+                .collect(),
         }
-        (moved, score)
     }
 
     #[must_use]
-    pub fn index_2d(i: usize, j: usize, w: usize, _h: usize) -> usize {
+    pub fn index_2d(i: usize, j: usize, w: usize, h: usize) -> usize {
+        debug_assert!(i < w && j < h, "({i}, {j}) is outside a {w}x{h} board");
         j * w + i
     }
 
     #[must_use]
-    pub fn index_to_coord(index: usize, w: usize, _h: usize) -> UVec2 {
+    pub fn index_to_coord(index: usize, w: usize, h: usize) -> UVec2 {
+        debug_assert!(index < w * h, "{index} is outside a {w}x{h} board");
         UVec2::new((index % w) as u32, (index / w) as u32)
     }
 
     pub fn has_legal_move(&self) -> bool {
-        let mut grid = *self;
-
-        let (up, _) = grid.move_up();
-        let (down, _) = grid.move_down();
-        let (left, _) = grid.move_left();
-        let (right, _) = grid.move_right();
-
-        !up.is_empty() || !down.is_empty() || !left.is_empty() || !right.is_empty()
+        Direction::ALL
+            .into_iter()
+            .any(|direction| self.clone().slide(direction).changed)
     }
 
     pub fn has_empty_cells(&self) -> bool {
@@ -229,8 +366,8 @@ impl Grid {
             != 0
     }
 
-    pub fn max_value(&self) -> usize {
-        self.cells.iter().max().cloned().unwrap()
+    pub fn max_value(&self) -> u64 {
+        rank_to_value(self.cells.iter().max().cloned().unwrap())
     }
 }
 
@@ -238,6 +375,7 @@ impl Grid {
 mod grid_tests {
 
     use super::*;
+    use crate::rng::GameRng;
 
     #[test]
     fn move_left_simple() {
@@ -248,7 +386,7 @@ mod grid_tests {
                                0, 0, 2, 0,
                                0, 0, 0, 2];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
         let res = [2, 0, 0, 0,
@@ -256,9 +394,9 @@ mod grid_tests {
                               2, 0, 0, 0,
                               2, 0, 0, 0];
 
-        let _ = grid.move_left();
+        let _ = grid.slide(Direction::Left);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -270,7 +408,7 @@ mod grid_tests {
                              8,  8,  8,  8,
                             16, 16, 16, 16];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
         let res = [4, 4, 0, 0,
@@ -278,9 +416,9 @@ mod grid_tests {
                     16, 16, 0, 0,
                     32, 32, 0, 0];
 
-        let _ = grid.move_left();
+        let _ = grid.slide(Direction::Left);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -292,7 +430,7 @@ mod grid_tests {
                   4, 0, 2, 0,
                   4, 0, 0, 2];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
         let res = [2, 4, 0, 0,
@@ -300,9 +438,9 @@ mod grid_tests {
                     4, 2, 0, 0,
                     4, 2, 0, 0];
 
-        let _ = grid.move_left();
+        let _ = grid.slide(Direction::Left);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -314,11 +452,11 @@ mod grid_tests {
                   4, 256, 2, 256,
                   4, 16, 8, 16];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
-        let _ = grid.move_left();
+        let _ = grid.slide(Direction::Left);
 
-        assert_eq!(grid.cells, test);
+        assert_eq!(grid.values(), test);
     }
 
     #[test]
@@ -330,7 +468,7 @@ mod grid_tests {
                   0, 0, 2, 0,
                   0, 0, 0, 2];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
         let res = [0, 0, 0, 2,
@@ -338,9 +476,9 @@ mod grid_tests {
                     0, 0, 0, 2,
                     0, 0, 0, 2];
 
-        let _ = grid.move_right();
+        let _ = grid.slide(Direction::Right);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -352,7 +490,7 @@ mod grid_tests {
                              8,  8,  8,  8,
                             16, 16, 16, 16];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
         let res = [ 0, 0,4, 4,
@@ -360,9 +498,9 @@ mod grid_tests {
                                0, 0, 16, 16,
                                0, 0, 32, 32,];
 
-        let _ = grid.move_right();
+        let _ = grid.slide(Direction::Right);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -374,7 +512,7 @@ mod grid_tests {
                   4, 0, 2, 0,
                   4, 0, 0, 2];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
         let res = [ 0, 0, 2, 4,
@@ -382,9 +520,9 @@ mod grid_tests {
                                0, 0, 4, 2,
                                0, 0, 4, 2,];
 
-        let _ = grid.move_right();
+        let _ = grid.slide(Direction::Right);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -396,11 +534,11 @@ mod grid_tests {
                   4, 256, 2, 256,
                   4, 16, 8, 16];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
-        let _ = grid.move_right();
+        let _ = grid.slide(Direction::Right);
 
-        assert_eq!(grid.cells, test);
+        assert_eq!(grid.values(), test);
     }
 
     #[test]
@@ -412,7 +550,7 @@ mod grid_tests {
                   0, 0, 2, 0,
                   0, 0, 0, 2];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
         let res = [2, 2, 2, 2,
@@ -420,9 +558,9 @@ mod grid_tests {
                     0, 0, 0, 0,
                     0, 0, 0, 0];
 
-        let _ = grid.move_down();
+        let _ = grid.slide(Direction::Down);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -435,7 +573,7 @@ mod grid_tests {
                              2, 4, 8, 16,
                              2, 4, 8, 16];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
         let res = [ 4, 8, 16, 32,
@@ -443,9 +581,9 @@ mod grid_tests {
                                0, 0,  0,  0,
                                0, 0,  0,  0];
 
-        let _ = grid.move_down();
+        let _ = grid.slide(Direction::Down);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -459,7 +597,7 @@ mod grid_tests {
         4, 0, 0, 2
       ];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
         let res = [2, 2, 4, 4,
@@ -467,9 +605,9 @@ mod grid_tests {
                     0, 0, 0, 0,
                     0, 0, 0, 0];
 
-        let _ = grid.move_down();
+        let _ = grid.slide(Direction::Down);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -481,11 +619,11 @@ mod grid_tests {
                   4, 256, 2, 256,
                   8, 16, 8, 16];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
-        let _ = grid.move_down();
+        let _ = grid.slide(Direction::Down);
 
-        assert_eq!(grid.cells, test);
+        assert_eq!(grid.values(), test);
     }
 
     #[test]
@@ -497,7 +635,7 @@ mod grid_tests {
                   0, 0, 2, 0,
                   0, 0, 0, 2];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
         let res = [0, 0, 0, 0,
@@ -505,9 +643,9 @@ mod grid_tests {
                     0, 0, 0, 0,
                     2, 2, 2, 2];
 
-        let _ = grid.move_up();
+        let _ = grid.slide(Direction::Up);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -519,7 +657,7 @@ mod grid_tests {
                              2, 4, 8, 16,
                              2, 4, 8, 16];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
       let res = [
@@ -529,9 +667,9 @@ mod grid_tests {
         4, 8, 16, 32,
                                ];
 
-        let _ = grid.move_up();
+        let _ = grid.slide(Direction::Up);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -544,7 +682,7 @@ mod grid_tests {
                   0, 0, 2, 0,
                   4, 0, 0, 2];
 
-        grid.cells = test;
+        grid.set_values(&test);
 
         #[rustfmt::skip]
       let res = [
@@ -552,9 +690,9 @@ mod grid_tests {
         0, 0, 0, 0,
         2, 2, 4, 4,
         4, 4, 2, 2];
-        let _ = grid.move_up();
+        let _ = grid.slide(Direction::Up);
 
-        assert_eq!(grid.cells, res);
+        assert_eq!(grid.values(), res);
     }
 
     #[test]
@@ -566,10 +704,405 @@ mod grid_tests {
                   4, 256, 2, 256,
                   8, 16, 8, 16];
 
-        grid.cells = test;
+        grid.set_values(&test);
+
+        let _ = grid.slide(Direction::Up);
+
+        assert_eq!(grid.values(), test);
+    }
+
+    #[test]
+    fn move_left_three_by_three() {
+        let mut grid = Grid::with_size(3, 3);
+        #[rustfmt::skip]
+        let test = [0, 2, 2,
+                    4, 0, 4,
+                    2, 4, 8];
+
+        grid.set_values(&test);
+
+        #[rustfmt::skip]
+        let res = [4, 0, 0,
+                   8, 0, 0,
+                   2, 4, 8];
 
-        let _ = grid.move_up();
+        let _ = grid.slide(Direction::Left);
 
-        assert_eq!(grid.cells, test);
+        assert_eq!(grid.values(), res);
+    }
+
+    #[test]
+    fn move_up_five_by_five() {
+        let mut grid = Grid::with_size(5, 5);
+        #[rustfmt::skip]
+        let test = [2, 0, 0, 0, 4,
+                    0, 0, 0, 0, 4,
+                    0, 0, 8, 0, 0,
+                    2, 0, 0, 0, 0,
+                    0, 0, 0, 0, 2];
+
+        grid.set_values(&test);
+
+        #[rustfmt::skip]
+        let res = [0, 0, 0, 0, 0,
+                   0, 0, 0, 0, 0,
+                   0, 0, 0, 0, 0,
+                   0, 0, 0, 0, 8,
+                   4, 0, 8, 0, 2];
+
+        let _ = grid.slide(Direction::Up);
+
+        assert_eq!(grid.values(), res);
+    }
+
+    #[test]
+    fn add_random_tile_fills_large_board() {
+        for size in [3, 5, 6, 8] {
+            let mut grid = Grid::with_size(size, size);
+            let mut rng = GameRng::from_seed(size as u64);
+            let mut placed = 0;
+            while let Some(coord) = grid.add_random_tile(&mut rng) {
+                assert!((coord.x as usize) < size && (coord.y as usize) < size);
+                placed += 1;
+            }
+            assert_eq!(placed, size * size);
+            assert!(!grid.has_empty_cells());
+        }
+    }
+
+    #[test]
+    fn add_random_tile_rectangular() {
+        for (width, height) in [(5, 4), (6, 3), (3, 6)] {
+            let mut grid = Grid::with_size(width, height);
+            let mut rng = GameRng::from_seed(0);
+            let mut placed = 0;
+            while let Some(coord) = grid.add_random_tile(&mut rng) {
+                assert!((coord.x as usize) < width && (coord.y as usize) < height);
+                placed += 1;
+            }
+            assert_eq!(placed, width * height);
+        }
+    }
+
+    #[test]
+    fn move_left_and_right_wide() {
+        let mut grid = Grid::with_size(5, 3);
+        #[rustfmt::skip]
+        let test = [2, 0, 2, 0, 8,
+                    0, 8, 0, 0, 8,
+                    4, 2, 0, 0, 0];
+
+        grid.set_values(&test);
+
+        #[rustfmt::skip]
+        let res = [4, 8, 0, 0, 0,
+                   16, 0, 0, 0, 0,
+                   4, 2, 0, 0, 0];
+
+        let _ = grid.slide(Direction::Left);
+
+        assert_eq!(grid.values(), res);
+
+        #[rustfmt::skip]
+        let res = [0, 0, 0, 4, 8,
+                   0, 0, 0, 0, 16,
+                   0, 0, 0, 4, 2];
+
+        let _ = grid.slide(Direction::Right);
+
+        assert_eq!(grid.values(), res);
+    }
+
+    #[test]
+    fn move_up_and_down_tall() {
+        let mut grid = Grid::with_size(2, 6);
+        #[rustfmt::skip]
+        let test = [2, 0,
+                    0, 4,
+                    2, 0,
+                    0, 0,
+                    8, 4,
+                    0, 2];
+
+        grid.set_values(&test);
+
+        #[rustfmt::skip]
+        let res = [0, 0,
+                   0, 0,
+                   0, 0,
+                   0, 0,
+                   4, 8,
+                   8, 2];
+
+        let _ = grid.slide(Direction::Up);
+
+        assert_eq!(grid.values(), res);
+
+        #[rustfmt::skip]
+        let res = [4, 8,
+                   8, 2,
+                   0, 0,
+                   0, 0,
+                   0, 0,
+                   0, 0];
+
+        let _ = grid.slide(Direction::Down);
+
+        assert_eq!(grid.values(), res);
+    }
+
+    #[test]
+    fn slide_reports_moves_and_score() {
+        let mut grid = Grid::new();
+        #[rustfmt::skip]
+        let test = [0, 2, 0, 2,
+                    0, 0, 0, 0,
+                    0, 0, 4, 0,
+                    0, 0, 0, 0];
+
+        grid.set_values(&test);
+
+        let outcome = grid.slide(Direction::Left);
+
+        let slide = |from: (u32, u32), to: (u32, u32)| TileSlide {
+            from: from.into(),
+            to: to.into(),
+        };
+        assert_eq!(
+            outcome.slides,
+            vec![
+                slide((1, 0), (0, 0)),
+                slide((3, 0), (0, 0)),
+                slide((2, 2), (0, 2)),
+            ]
+        );
+        assert_eq!(
+            outcome.merges,
+            vec![TileMerge {
+                survivor: UVec2::new(1, 0),
+                absorbed: UVec2::new(3, 0),
+                to: UVec2::new(0, 0),
+                value: 4,
+            }]
+        );
+        assert_eq!(outcome.score, 4);
+        assert!(outcome.changed);
+    }
+
+    #[test]
+    fn has_legal_move_full_board() {
+        let mut grid = Grid::new();
+        #[rustfmt::skip]
+        let test = [8, 2, 16, 4,
+                    2, 4, 16, 8,
+                    4, 256, 2, 256,
+                    4, 16, 8, 16];
+
+        grid.set_values(&test);
+        assert!(grid.has_legal_move());
+
+        grid.cells[8] = 5;
+        grid.cells[6] = 6;
+        assert!(!grid.has_legal_move());
+    }
+
+    #[test]
+    fn move_left_merged_tile_blocks_next() {
+        let mut grid = Grid::new();
+        #[rustfmt::skip]
+        let test = [2, 2, 4, 0,
+                    4, 4, 8, 8,
+                    2, 0, 2, 4,
+                    0, 4, 4, 4];
+
+        grid.set_values(&test);
+
+        #[rustfmt::skip]
+        let res = [4, 4, 0, 0,
+                   8, 16, 0, 0,
+                   4, 4, 0, 0,
+                   8, 4, 0, 0];
+
+        let outcome = grid.slide(Direction::Left);
+
+        assert_eq!(grid.values(), res);
+        assert_eq!(outcome.score, 4 + 8 + 16 + 4 + 8);
+        let mut values = outcome.merges.iter().map(|m| m.value).collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, [4, 4, 8, 8, 16]);
+    }
+
+    /// Reference model: compress a line towards its start, merging each pair
+    /// of equal neighbours at most once.
+    fn compress_line(line: &[u64]) -> (Vec<u64>, u64) {
+        let tiles = line.iter().copied().filter(|&v| v != 0).collect::<Vec<_>>();
+        let mut res = Vec::new();
+        let mut score = 0;
+        let mut i = 0;
+        while i < tiles.len() {
+            if i + 1 < tiles.len() && tiles[i] == tiles[i + 1] {
+                res.push(tiles[i] * 2);
+                score += tiles[i] * 2;
+                i += 2;
+            } else {
+                res.push(tiles[i]);
+                i += 1;
+            }
+        }
+        res.resize(line.len(), 0);
+        (res, score)
+    }
+
+    #[test]
+    fn slide_matches_reference_for_every_row() {
+        // Every row of four cells holding either nothing or 2..=2^15.
+        for code in 0..(1 << 16) {
+            let row = (0..4)
+                .map(|k| match (code >> (4 * k)) & 0xf {
+                    0 => 0,
+                    exponent => 1 << exponent,
+                })
+                .collect::<Vec<u64>>();
+
+            let (forward, score) = compress_line(&row);
+            let reversed = row.iter().rev().copied().collect::<Vec<_>>();
+            let (mut backward, _) = compress_line(&reversed);
+            backward.reverse();
+
+            for direction in Direction::ALL {
+                let mut values = [0; 16];
+                let mut expected = [0; 16];
+                let line = match direction {
+                    Direction::Left | Direction::Down => &forward,
+                    Direction::Right | Direction::Up => &backward,
+                };
+                // Place the same line in every row (or column) at once.
+                for a in 0..4 {
+                    for b in 0..4 {
+                        let index = match direction {
+                            Direction::Left | Direction::Right => Grid::index_2d(b, a, 4, 4),
+                            Direction::Up | Direction::Down => Grid::index_2d(a, b, 4, 4),
+                        };
+                        values[index] = row[b];
+                        expected[index] = line[b];
+                    }
+                }
+
+                let mut grid = Grid::from_values(4, 4, &values);
+                let outcome = grid.slide(direction);
+
+                assert_eq!(grid.values(), expected, "{row:?} {direction:?}");
+                assert_eq!(outcome.score, 4 * score, "{row:?} {direction:?}");
+                assert_eq!(outcome.changed, *line != row, "{row:?} {direction:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_spawns() {
+        let spawns = |seed| {
+            let mut grid = Grid::new();
+            let mut rng = GameRng::from_seed(seed);
+            let mut spawns = Vec::new();
+            while let Some(coord) = grid.add_random_tile(&mut rng) {
+                spawns.push((coord, grid.value_at(coord)));
+            }
+            spawns
+        };
+
+        assert_eq!(spawns(7), spawns(7));
+        assert_ne!(spawns(7), spawns(8));
+    }
+
+    #[test]
+    fn seeded_spawns_are_stable() {
+        // Pinned output: if this changes, recorded games no longer replay.
+        let mut grid = Grid::new();
+        let mut rng = GameRng::from_seed(2048);
+        for _ in 0..6 {
+            let _ = grid.add_random_tile(&mut rng);
+        }
+
+        #[rustfmt::skip]
+        let res = [2, 0, 0, 0,
+                   0, 2, 2, 2,
+                   0, 2, 0, 0,
+                   0, 4, 0, 0];
+
+        assert_eq!(grid.values(), res);
+    }
+
+    #[test]
+    fn easy_rules_only_spawn_twos() {
+        let mut grid = Grid::new().with_rules(SpawnRules::easy());
+        let mut rng = GameRng::from_seed(1);
+        while grid.add_random_tile(&mut rng).is_some() {}
+
+        assert!(grid.values().iter().all(|&v| v == 2));
+    }
+
+    #[test]
+    fn spawn_counts_follow_rules() {
+        let rules = SpawnRules {
+            values: vec![(2, 1.0), (4, 1.0)],
+            per_move: 3,
+            starting: 5,
+        };
+        let mut grid = Grid::new().with_rules(rules);
+        let mut rng = GameRng::from_seed(3);
+
+        assert_eq!(grid.start(&mut rng).len(), 5);
+        assert_eq!(grid.spawn_tiles(&mut rng).len(), 3);
+        assert_eq!(grid.cells.iter().filter(|&&c| c != 0).count(), 8);
+        assert!(grid.values().iter().all(|v| [0, 2, 4].contains(v)));
+
+        // Only what fits is placed on a nearly full board.
+        for _ in 0..6 {
+            let _ = grid.add_random_tile(&mut rng);
+        }
+        assert_eq!(grid.spawn_tiles(&mut rng).len(), 2);
+        assert!(grid.spawn_tiles(&mut rng).is_empty());
+    }
+
+    #[test]
+    fn pick_value_follows_weights() {
+        let rules = SpawnRules {
+            values: vec![(2, 3.0), (4, 0.0), (8, 1.0)],
+            ..SpawnRules::default()
+        };
+        let mut rng = GameRng::from_seed(5);
+        let picks = (0..10_000)
+            .map(|_| rules.pick_value(&mut rng))
+            .collect::<Vec<_>>();
+        let eights = picks.iter().filter(|&&v| v == 8).count();
+
+        assert!(!picks.contains(&4));
+        assert!((2000..3000).contains(&eights), "{eights}");
+    }
+
+    #[test]
+    fn huge_tiles_on_large_boards() {
+        // 2^40 + 2^40 would not fit in a u32, let alone in the old cells.
+        let mut grid = Grid::with_size(8, 8);
+        grid.cells[0] = 40;
+        grid.cells[1] = 40;
+        let outcome = grid.slide(Direction::Left);
+
+        assert_eq!(grid.max_value(), 1 << 41);
+        assert_eq!(outcome.score, 1 << 41);
+        assert_eq!(outcome.merges[0].value, 1 << 41);
+        assert_eq!(grid.value_at(UVec2::new(0, 0)), 1 << 41);
+    }
+
+    #[test]
+    fn ranks_and_values() {
+        assert_eq!(rank_to_value(0), 0);
+        assert_eq!(rank_to_value(1), 2);
+        assert_eq!(rank_to_value(11), 2048);
+        assert_eq!(rank_to_value(200), u64::MAX);
+        assert_eq!(value_to_rank(0), Some(0));
+        assert_eq!(value_to_rank(2048), Some(11));
+        assert_eq!(value_to_rank(1), None);
+        assert_eq!(value_to_rank(6), None);
     }
 }
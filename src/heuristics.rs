@@ -0,0 +1,277 @@
+//! Measures of how good a board looks, for bots and hints.
+//!
+//! Each metric reads tile ranks (exponents) rather than values, so a 4096
+//! next to a 2048 is one step apart like a 4 next to a 2. They work on any
+//! [`Cells`]: a [`Grid`], or a [`Bitboard`] in search code. [`score`] adds
+//! them up with [`Weights`].
+
+use crate::bitboard::Bitboard;
+use crate::grid::Grid;
+
+/// A board the metrics can read.
+pub trait Cells {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    /// Rank of the tile at `(i, j)`, 0 for an empty cell.
+    fn cell(&self, i: usize, j: usize) -> u8;
+}
+
+impl Cells for Grid {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn cell(&self, i: usize, j: usize) -> u8 {
+        self.cells[Grid::index_2d(i, j, self.width, self.height)]
+    }
+}
+
+impl Cells for Bitboard {
+    fn width(&self) -> usize {
+        4
+    }
+
+    fn height(&self) -> usize {
+        4
+    }
+
+    fn cell(&self, i: usize, j: usize) -> u8 {
+        self.rank(Grid::index_2d(i, j, 4, 4))
+    }
+}
+
+fn ranks(board: &impl Cells) -> impl Iterator<Item = u8> + '_ {
+    (0..board.height()).flat_map(move |j| (0..board.width()).map(move |i| board.cell(i, j)))
+}
+
+/// The ranks of every row, bottom first, then of every column, left first.
+fn lines(board: &impl Cells) -> impl Iterator<Item = Vec<u8>> + '_ {
+    let (w, h) = (board.width(), board.height());
+    let rows = (0..h).map(move |j| (0..w).map(|i| board.cell(i, j)).collect());
+    let columns = (0..w).map(move |i| (0..h).map(|j| board.cell(i, j)).collect());
+    rows.chain(columns)
+}
+
+/// The tiles of a line, without the gaps between them.
+fn tiles(line: &[u8]) -> impl Iterator<Item = u8> + Clone + '_ {
+    line.iter().copied().filter(|&rank| rank != 0)
+}
+
+/// Cells with no tile.
+#[must_use]
+pub fn empty_cells(board: &impl Cells) -> usize {
+    ranks(board).filter(|&rank| rank == 0).count()
+}
+
+/// Rank of the largest tile.
+#[must_use]
+pub fn max_rank(board: &impl Cells) -> u8 {
+    ranks(board).max().unwrap_or(0)
+}
+
+/// How far rows and columns are from running one way: 0 when the tiles of
+/// every line only grow or only shrink along it, more negative the more
+/// they go up and down. Gaps are skipped.
+#[must_use]
+pub fn monotonicity(board: &impl Cells) -> f32 {
+    let mut penalty = 0;
+    for line in lines(board) {
+        let (mut rises, mut falls) = (0u32, 0u32);
+        let mut tiles = tiles(&line);
+        let Some(mut previous) = tiles.next() else {
+            continue;
+        };
+        for rank in tiles {
+            if rank > previous {
+                rises += u32::from(rank - previous);
+            } else {
+                falls += u32::from(previous - rank);
+            }
+            previous = rank;
+        }
+        penalty += rises.min(falls);
+    }
+    -(penalty as f32)
+}
+
+/// How alike neighbouring tiles are: minus the rank differences between
+/// tiles that would meet in a slide, 0 when they are all equal.
+#[must_use]
+pub fn smoothness(board: &impl Cells) -> f32 {
+    let mut penalty = 0;
+    for line in lines(board) {
+        let tiles = tiles(&line);
+        penalty += tiles
+            .clone()
+            .zip(tiles.skip(1))
+            .map(|(a, b)| u32::from(a.abs_diff(b)))
+            .sum::<u32>();
+    }
+    -(penalty as f32)
+}
+
+/// Rank of the largest tile if one sits in a corner, where it is safest;
+/// 0 otherwise.
+#[must_use]
+pub fn corner_anchoring(board: &impl Cells) -> f32 {
+    let (w, h) = (board.width(), board.height());
+    let max_rank = max_rank(board);
+    let cornered = [(0, 0), (w - 1, 0), (0, h - 1), (w - 1, h - 1)]
+        .into_iter()
+        .any(|(i, j)| board.cell(i, j) == max_rank);
+    if cornered && max_rank > 0 {
+        f32::from(max_rank)
+    } else {
+        0.0
+    }
+}
+
+/// Pairs of equal tiles that would merge if the board slid along their row
+/// or column, counting rows and columns together.
+#[must_use]
+pub fn merge_potential(board: &impl Cells) -> usize {
+    let mut merges = 0;
+    for line in lines(board) {
+        let mut tiles = tiles(&line).peekable();
+        while let Some(rank) = tiles.next() {
+            // Like a slide, a tile merges once.
+            if tiles.next_if_eq(&rank).is_some() {
+                merges += 1;
+            }
+        }
+    }
+    merges
+}
+
+/// How much each metric counts in [`score`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Weights {
+    pub empty_cells: f32,
+    pub monotonicity: f32,
+    pub smoothness: f32,
+    pub corner_anchoring: f32,
+    pub merge_potential: f32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            empty_cells: 2.7,
+            monotonicity: 1.0,
+            smoothness: 0.1,
+            corner_anchoring: 1.0,
+            merge_potential: 0.7,
+        }
+    }
+}
+
+/// The metrics summed with `weights`; higher is better.
+#[must_use]
+pub fn score(board: &impl Cells, weights: &Weights) -> f32 {
+    weights.empty_cells * empty_cells(board) as f32
+        + weights.monotonicity * monotonicity(board)
+        + weights.smoothness * smoothness(board)
+        + weights.corner_anchoring * corner_anchoring(board)
+        + weights.merge_potential * merge_potential(board) as f32
+}
+
+#[cfg(test)]
+mod heuristics_tests {
+    use super::*;
+
+    /// Rows are given top first, as they look on screen.
+    fn board(rows: &[&[u64]]) -> Grid {
+        let values: Vec<u64> = rows
+            .iter()
+            .rev()
+            .flat_map(|row| row.iter())
+            .copied()
+            .collect();
+        Grid::from_values(rows[0].len(), rows.len(), &values)
+    }
+
+    #[test]
+    fn counts_empty_cells_and_merges() {
+        #[rustfmt::skip]
+        let grid = board(&[
+            &[2, 2, 2, 2],
+            &[4, 0, 0, 4],
+            &[0, 8, 16, 0],
+            &[0, 0, 0, 0],
+        ]);
+        assert_eq!(empty_cells(&grid), 8);
+        assert_eq!(max_rank(&grid), 4);
+        // Two in the top row, one across the gap below it, none in columns.
+        assert_eq!(merge_potential(&grid), 3);
+
+        let column = board(&[&[2, 4], &[2, 0], &[2, 4]]);
+        assert_eq!(merge_potential(&column), 2);
+    }
+
+    #[test]
+    fn monotonic_boards_score_zero() {
+        let snake = board(&[
+            &[2, 4, 8, 16],
+            &[0, 2, 4, 32],
+            &[0, 0, 2, 64],
+            &[0, 0, 0, 128],
+        ]);
+        assert_eq!(monotonicity(&snake), 0.0);
+
+        #[rustfmt::skip]
+        let zigzag = board(&[
+            &[2, 64, 2, 64],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+        ]);
+        // Rises of 5 + 5 against a fall of 5 along the top row.
+        assert_eq!(monotonicity(&zigzag), -5.0);
+    }
+
+    #[test]
+    fn smooth_boards_beat_rough_ones() {
+        let flat = board(&[&[4, 4], &[4, 4]]);
+        assert_eq!(smoothness(&flat), 0.0);
+
+        let rough = board(&[&[2, 1024], &[1024, 2]]);
+        let gentle = board(&[&[2, 4], &[4, 8]]);
+        assert_eq!(smoothness(&gentle), -4.0);
+        assert!(smoothness(&rough) < smoothness(&gentle));
+    }
+
+    #[test]
+    fn the_largest_tile_belongs_in_a_corner() {
+        let cornered = board(&[&[0, 0, 0], &[0, 2, 0], &[0, 0, 256]]);
+        assert_eq!(corner_anchoring(&cornered), 8.0);
+
+        let centred = board(&[&[0, 0, 0], &[0, 256, 0], &[0, 0, 2]]);
+        assert_eq!(corner_anchoring(&centred), 0.0);
+        assert_eq!(corner_anchoring(&Grid::new()), 0.0);
+    }
+
+    #[test]
+    fn tidy_boards_score_higher() {
+        let tidy = board(&[
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 2],
+            &[0, 2, 4, 8],
+            &[16, 32, 64, 128],
+        ]);
+        let messy = board(&[
+            &[2, 0, 64, 0],
+            &[0, 128, 0, 4],
+            &[32, 0, 16, 0],
+            &[0, 8, 0, 2],
+        ]);
+        let weights = Weights::default();
+        assert!(score(&tidy, &weights) > score(&messy, &weights));
+
+        let packed = Bitboard::from_grid(&tidy).unwrap();
+        assert_eq!(score(&packed, &weights), score(&tidy, &weights));
+    }
+}
@@ -0,0 +1,141 @@
+//! Asking the solver for a move.
+//!
+//! H or the Hint button starts a search of the board on the async compute
+//! pool, so the game keeps running; when it is done an arrow over the board
+//! points the way until the board changes.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::tasks::{block_on, poll_once, AsyncComputeTaskPool, Task};
+use shadowmitia_2048::grid::{Direction, Grid};
+use shadowmitia_2048::solver::Solver;
+
+use crate::{grid_coord_to_position, AppState, GameStats, Layout};
+
+/// Ask for a hint on the board as it is.
+#[derive(Event, Clone, Copy)]
+pub struct HintRequest;
+
+/// The search under way or its answer, for the board it was asked on.
+#[derive(Resource, Default)]
+struct Hint {
+    cells: Vec<u8>,
+    task: Option<Task<Option<Direction>>>,
+    shown: Option<Direction>,
+}
+
+#[derive(Default, Reflect, GizmoConfigGroup)]
+struct HintGizmos;
+
+pub fn plugin(app: &mut App) {
+    app.add_event::<HintRequest>()
+        .init_resource::<Hint>()
+        .init_gizmo_group::<HintGizmos>()
+        .add_systems(Startup, configure_gizmos)
+        .add_systems(
+            Update,
+            (
+                hint_keys,
+                request_hint,
+                poll_hint,
+                forget_hint.run_if(resource_changed::<Grid>),
+                draw_hint,
+            )
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        );
+}
+
+fn configure_gizmos(mut config_store: ResMut<GizmoConfigStore>) {
+    let (config, _) = config_store.config_mut::<HintGizmos>();
+    config.line_width = 12.0;
+}
+
+fn hint_keys(input: Res<ButtonInput<KeyCode>>, mut requests: EventWriter<HintRequest>) {
+    if input.just_pressed(KeyCode::KeyH) {
+        requests.send(HintRequest);
+    }
+}
+
+/// Start a search unless one is already running for this board.
+fn request_hint(
+    mut requests: EventReader<HintRequest>,
+    grid: Res<Grid>,
+    mut hint: ResMut<Hint>,
+    mut stats: ResMut<GameStats>,
+) {
+    if requests.read().count() == 0 {
+        return;
+    }
+    if hint.cells == grid.cells && (hint.task.is_some() || hint.shown.is_some()) {
+        return;
+    }
+
+    stats.hints += 1;
+    let board = grid.clone();
+    let solver = Solver {
+        depth: 4,
+        time: Some(Duration::from_millis(250)),
+        ..default()
+    };
+    *hint = Hint {
+        cells: grid.cells.clone(),
+        task: Some(AsyncComputeTaskPool::get().spawn(async move { solver.best_move(&board) })),
+        shown: None,
+    };
+}
+
+fn poll_hint(mut hint: ResMut<Hint>) {
+    let Some(task) = &mut hint.task else {
+        return;
+    };
+    let Some(direction) = block_on(poll_once(task)) else {
+        return;
+    };
+    hint.task = None;
+    match direction {
+        Some(direction) => hint.shown = Some(direction),
+        None => info!("no move left to hint at"),
+    }
+}
+
+/// A hint only holds for the board it was asked on; a search still running
+/// for an older board is dropped, which cancels it.
+fn forget_hint(grid: Res<Grid>, mut hint: ResMut<Hint>) {
+    if hint.cells != grid.cells {
+        *hint = Hint::default();
+    }
+}
+
+fn draw_hint(
+    hint: Res<Hint>,
+    grid: Res<Grid>,
+    layout: Res<Layout>,
+    mut gizmos: Gizmos<HintGizmos>,
+) {
+    let Some(direction) = hint.shown else {
+        return;
+    };
+    let centre = grid_coord_to_position(
+        &layout,
+        Vec3::new(
+            (grid.width - 1) as f32 / 2.0,
+            (grid.height - 1) as f32 / 2.0,
+            0.0,
+        ),
+    )
+    .truncate();
+    let towards = match direction {
+        Direction::Left => Vec2::NEG_X,
+        Direction::Right => Vec2::X,
+        Direction::Up => Vec2::Y,
+        Direction::Down => Vec2::NEG_Y,
+    };
+    let reach = layout.cell_size.x * grid.width.min(grid.height) as f32 * 0.3;
+    gizmos.arrow_2d(
+        centre - towards * reach,
+        centre + towards * reach,
+        Color::rgba(0.2, 0.2, 0.2, 0.8),
+    );
+}
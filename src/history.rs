@@ -0,0 +1,208 @@
+//! Undo and redo of moves.
+//!
+//! Every move records the state from before it: the board (and so the
+//! tiles it spawned), the generator, and the points it scored. Restoring the
+//! generator means replaying a move after undoing it spawns the same tiles.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::grid::Grid;
+use crate::rng::GameRng;
+
+/// The game on one side of a move.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub grid: Grid,
+    pub rng: GameRng,
+    /// Points scored by the move between this state and the current one.
+    pub score_delta: u64,
+    /// Largest milestone celebrated so far.
+    pub has_won: u64,
+}
+
+#[derive(Resource, Default)]
+pub struct History {
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// Undos allowed per game, `None` for no limit.
+    limit: Option<usize>,
+    used: usize,
+}
+
+impl History {
+    #[must_use]
+    pub fn with_limit(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    /// Forget everything, for a new game.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.used = 0;
+    }
+
+    /// Undos left this game, `None` if unlimited.
+    #[must_use]
+    pub fn remaining(&self) -> Option<usize> {
+        self.limit.map(|limit| limit.saturating_sub(self.used))
+    }
+
+    #[must_use]
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() && self.remaining() != Some(0)
+    }
+
+    #[must_use]
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Record the state from before a move. Drops the redo stack, and the
+    /// oldest entries that could not be undone anyway.
+    pub fn record(&mut self, before: Snapshot) {
+        self.redo.clear();
+        self.undo.push_back(before);
+        if let Some(remaining) = self.remaining() {
+            while self.undo.len() > remaining {
+                self.undo.pop_front();
+            }
+        }
+    }
+
+    /// Swap `current` for the state before the last move.
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        if !self.can_undo() {
+            return None;
+        }
+        let previous = self.undo.pop_back()?;
+        self.used += 1;
+        self.redo.push(Snapshot {
+            score_delta: previous.score_delta,
+            ..current
+        });
+        Some(previous)
+    }
+
+    /// Swap `current` for the state after the last undone move.
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(Snapshot {
+            score_delta: next.score_delta,
+            ..current
+        });
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod history_tests {
+    use super::*;
+    use crate::grid::Direction;
+
+    fn snapshot(grid: &Grid, rng: &GameRng, score_delta: u64) -> Snapshot {
+        Snapshot {
+            grid: grid.clone(),
+            rng: rng.clone(),
+            score_delta,
+            has_won: 0,
+        }
+    }
+
+    #[test]
+    fn undo_then_redo_restores_the_move() {
+        let mut rng = GameRng::from_seed(3);
+        let mut grid = Grid::new();
+        grid.start(&mut rng);
+        let mut history = History::default();
+
+        let before = snapshot(&grid, &rng, 0);
+        let outcome = grid.slide(Direction::Left);
+        grid.spawn_tiles(&mut rng);
+        history.record(Snapshot {
+            score_delta: outcome.score,
+            ..before.clone()
+        });
+        let after = grid.values();
+
+        let current = Snapshot {
+            has_won: 2048,
+            ..snapshot(&grid, &rng, 0)
+        };
+        let undone = history.undo(current).unwrap();
+        assert_eq!(undone.grid.values(), before.grid.values());
+        assert_eq!((undone.score_delta, undone.has_won), (outcome.score, 0));
+        assert!(!history.can_undo());
+
+        let redone = history.redo(undone).unwrap();
+        assert_eq!(redone.grid.values(), after);
+        assert_eq!((redone.score_delta, redone.has_won), (outcome.score, 2048));
+        assert!(history.can_undo());
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn replaying_an_undone_move_spawns_the_same_tiles() {
+        let mut rng = GameRng::from_seed(9);
+        let mut grid = Grid::new();
+        grid.start(&mut rng);
+        let mut history = History::default();
+
+        history.record(snapshot(&grid, &rng, 0));
+        let _ = grid.slide(Direction::Down);
+        grid.spawn_tiles(&mut rng);
+        let first = grid.values();
+
+        let Snapshot {
+            grid: mut replay,
+            rng: mut replay_rng,
+            ..
+        } = history.undo(snapshot(&grid, &rng, 0)).unwrap();
+        let _ = replay.slide(Direction::Down);
+        replay.spawn_tiles(&mut replay_rng);
+        assert_eq!(replay.values(), first);
+    }
+
+    #[test]
+    fn new_move_drops_redo() {
+        let grid = Grid::new();
+        let rng = GameRng::from_seed(0);
+        let mut history = History::default();
+
+        history.record(snapshot(&grid, &rng, 4));
+        history.undo(snapshot(&grid, &rng, 0)).unwrap();
+        assert!(history.can_redo());
+
+        history.record(snapshot(&grid, &rng, 8));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn limit_counts_undos_per_game() {
+        let grid = Grid::new();
+        let rng = GameRng::from_seed(0);
+        let mut history = History::with_limit(Some(2));
+
+        for _ in 0..5 {
+            history.record(snapshot(&grid, &rng, 0));
+        }
+        assert_eq!(history.remaining(), Some(2));
+        assert!(history.undo(snapshot(&grid, &rng, 0)).is_some());
+        assert!(history.undo(snapshot(&grid, &rng, 0)).is_some());
+        assert!(history.undo(snapshot(&grid, &rng, 0)).is_none());
+
+        // Redoing does not give undos back.
+        assert!(history.redo(snapshot(&grid, &rng, 0)).is_some());
+        assert!(!history.can_undo());
+
+        history.clear();
+        history.record(snapshot(&grid, &rng, 0));
+        assert!(history.can_undo());
+        assert_eq!(history.remaining(), Some(2));
+    }
+}
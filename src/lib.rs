@@ -0,0 +1,14 @@
+pub mod bitboard;
+pub mod grid;
+pub mod heuristics;
+pub mod history;
+pub mod notation;
+pub mod playback;
+pub mod recording;
+pub mod rng;
+pub mod rollout;
+pub mod save;
+pub mod share;
+pub mod solver;
+pub mod storage;
+pub mod strategy;
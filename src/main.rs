@@ -1,104 +1,215 @@
+use std::time::Duration;
+
+use bevy::app::AppExit;
 use bevy::prelude::*;
-use grid::*;
+use bevy::time::common_conditions::on_timer;
+use options::*;
+use shadowmitia_2048::grid::{rank_to_value, Direction, Grid, MoveOutcome, TileSlide};
+use shadowmitia_2048::history::{History, Snapshot};
+use shadowmitia_2048::recording::{Recording, Replays};
+use shadowmitia_2048::rng::GameRng;
+use shadowmitia_2048::save::SavedGame;
+use shadowmitia_2048::storage::{Scores, Storage};
 use tween::*;
 
-mod grid;
+mod autoplay;
+mod hint;
+mod options;
+mod replay;
+mod share_dialog;
 mod tween;
 
-const WINDOW_SIZE: Vec2 = Vec2::new(800.0, 800.0 + 800.0 * 110.0 / 1000.0);
-const CELL_SIZE: Vec2 = Vec2::new(200.0, 200.0);
+/// Width and height of the board area, whatever the number of cells.
+const BOARD_EXTENT: f32 = 800.0;
+const HEADER_HEIGHT: f32 = BOARD_EXTENT * 110.0 / 1000.0;
 
 const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
 const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
 
+/// Longest a move goes unsaved while playing. The game is also saved when
+/// leaving play and on exit.
+const SAVE_INTERVAL: Duration = Duration::from_secs(2);
+
 #[derive(States, Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
 enum AppState {
     GameOver,
     Win,
     #[default]
     InGame,
+    /// Watching a recorded game; the one in progress waits.
+    Replay,
+    /// Sharing the position, or typing in another one.
+    Share,
 }
 
 #[derive(Default, Event)]
-struct ScoreEvent(u32);
+struct ScoreEvent(u64);
 
 #[derive(Resource, Default)]
 struct Score {
-    current: u32,
-    highscore: u32,
+    current: u64,
+    highscore: u64,
+    /// The game carries on from a shared position, whose score could be
+    /// anything, so it does not count towards the high score.
+    imported: bool,
+}
+
+/// Running totals for the current game.
+#[derive(Resource, Default)]
+struct GameStats {
+    moves: u64,
+    hints: u64,
 }
 
+/// Largest milestone already celebrated this game, 0 if none.
 #[derive(Resource, Default)]
-struct HasWon(bool);
+struct HasWon(u64);
+
+/// Tile values that bring up the win screen, each at most once per game.
+/// No milestones means endless mode.
+#[derive(Resource, Clone)]
+struct WinCondition {
+    milestones: Vec<u64>,
+}
+
+impl WinCondition {
+    /// The milestone to celebrate now that the largest tile is `max_value`,
+    /// if one was reached since the last celebration.
+    fn reached(&self, celebrated: u64, max_value: u64) -> Option<u64> {
+        self.milestones
+            .iter()
+            .copied()
+            .filter(|&m| m > celebrated && m <= max_value)
+            .max()
+    }
+
+    /// The last milestone: the game pauses on reaching it, without spawning.
+    fn target(&self) -> Option<u64> {
+        self.milestones.iter().copied().max()
+    }
+}
+
+/// Screen-space dimensions derived from the board size.
+#[derive(Resource, Clone, Copy)]
+struct Layout {
+    cell_size: Vec2,
+    window_size: Vec2,
+}
+
+impl Layout {
+    /// Cells stay square, so the longer side of the board fills `BOARD_EXTENT`.
+    fn new(width: usize, height: usize) -> Self {
+        let cell = BOARD_EXTENT / width.max(height) as f32;
+        Self {
+            cell_size: Vec2::new(cell, cell),
+            window_size: Vec2::new(cell * width as f32, cell * height as f32 + HEADER_HEIGHT),
+        }
+    }
+
+    fn tile_font_size(&self) -> f32 {
+        self.cell_size.y * 0.3
+    }
+}
+
+/// Whether a `width` by `height` board can take the place of `grid` on
+/// screen. The window is laid out once, for the board being played.
+fn fits_window(grid: &Grid, width: usize, height: usize) -> bool {
+    (width, height) == (grid.width, grid.height)
+}
 
 #[must_use]
-pub fn score_to_colour(score: u32) -> Color {
-    match score {
+pub fn rank_to_colour(rank: u8) -> Color {
+    match rank {
         0 => Color::hex("cdc1b4").unwrap(),
-        2 => Color::hex("eee4da").unwrap(),
-        4 => Color::hex("ede0c8").unwrap(),
-        8 => Color::hex("f2b179").unwrap(),
-        16 => Color::hex("f59563").unwrap(),
-        32 => Color::hex("f67c5f").unwrap(),
-        64 => Color::hex("f65e3b").unwrap(),
-        128 => Color::hex("edcf72").unwrap(),
-        256 => Color::hex("edcc61").unwrap(),
-        512 => Color::hex("edc850").unwrap(),
-        1024 => Color::hex("edc53f").unwrap(),
-        2048 => Color::hex("edc22e").unwrap(),
+        1 => Color::hex("eee4da").unwrap(),
+        2 => Color::hex("ede0c8").unwrap(),
+        3 => Color::hex("f2b179").unwrap(),
+        4 => Color::hex("f59563").unwrap(),
+        5 => Color::hex("f67c5f").unwrap(),
+        6 => Color::hex("f65e3b").unwrap(),
+        7 => Color::hex("edcf72").unwrap(),
+        8 => Color::hex("edcc61").unwrap(),
+        9 => Color::hex("edc850").unwrap(),
+        10 => Color::hex("edc53f").unwrap(),
+        11 => Color::hex("edc22e").unwrap(),
         _ => Color::hex("FF00FF").unwrap(),
     }
 }
 
 #[must_use]
-fn grid_coord_to_position(v: Vec3) -> Vec3 {
+fn grid_coord_to_position(layout: &Layout, v: Vec3) -> Vec3 {
+    let Layout {
+        cell_size,
+        window_size,
+    } = *layout;
     let mut transform = Vec3::new(0.0, 0.0, 0.0);
-    transform.x += CELL_SIZE.x * v.x - WINDOW_SIZE.x / 2.0 + CELL_SIZE.x / 2.0;
-    transform.y += CELL_SIZE.y * v.y - WINDOW_SIZE.y / 2.0 + CELL_SIZE.y / 2.0;
+    transform.x += cell_size.x * v.x - window_size.x / 2.0 + cell_size.x / 2.0;
+    transform.y += cell_size.y * v.y - window_size.y / 2.0 + cell_size.y / 2.0;
     transform.z = v.z;
     transform
 }
 
-#[must_use]
-fn add_tile(commands: &mut Commands, grid: &mut Grid, text_style: &TextStyle) -> bool {
-    if let Some(UVec2 { x: i, y: j }) = grid.add_random_tile() {
-        let score = grid.cells[Grid::index_2d(i as usize, j as usize, 4, 4)] as u32;
-        commands.spawn((
+fn spawn_tile(
+    commands: &mut Commands,
+    layout: &Layout,
+    text_style: &TextStyle,
+    coord: UVec2,
+    rank: u8,
+) {
+    // The tile's number lives in a child so both move, scale and
+    // despawn together.
+    commands
+        .spawn((
             SpriteBundle {
                 sprite: Sprite {
-                    color: score_to_colour(score),
-                    custom_size: Some(CELL_SIZE),
+                    color: rank_to_colour(rank),
+                    custom_size: Some(layout.cell_size),
                     ..Default::default()
                 },
                 transform: Transform {
-                    translation: grid_coord_to_position(Vec3::new(i as f32, j as f32, 0 as f32)),
+                    translation: grid_coord_to_position(
+                        layout,
+                        Vec3::new(coord.x as f32, coord.y as f32, 0 as f32),
+                    ),
                     ..Default::default()
                 },
                 ..Default::default()
             },
             tween_scale(0.2, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 1.0, 1.0)),
-            Cell {
-                coord: UVec2 { x: i, y: j },
-            },
-        ));
+            Cell { coord },
+        ))
+        .with_children(|builder| {
+            builder.spawn(Text2dBundle {
+                text: Text::from_section(rank_to_value(rank).to_string(), text_style.clone()),
+                transform: Transform::from_xyz(0.0, 0.0, 1.0),
+                ..Default::default()
+            });
+        });
+}
 
-        let c = grid_coord_to_position(Vec3::new(i as f32, j as f32, 0.));
+/// A move asked for by the player, whatever the input device.
+#[derive(Event, Clone, Copy)]
+struct MoveRequest(Direction);
 
-        commands.spawn((
-            Text2dBundle {
-                text: Text::from_section(score.to_string(), text_style.clone()),
-                transform: Transform::from_xyz(c.x, c.y, 2.0),
+/// Sent whenever the board is changed by a slide, so the tiles can be
+/// animated instead of being rebuilt.
+#[derive(Event)]
+struct TilesMoved(MoveOutcome);
 
-                ..Default::default()
-            },
-            Cell {
-                coord: UVec2 { x: i, y: j },
-            },
-        ));
-        return true;
-    }
-    false
+/// Take back the last move, or play it again.
+#[derive(Event, Clone, Copy)]
+enum HistoryRequest {
+    Undo,
+    Redo,
+}
+
+/// Header buttons, usable while playing.
+#[derive(Component, Clone, Copy)]
+enum HudButton {
+    History(HistoryRequest),
+    Hint,
+    Share,
 }
 
 #[derive(Resource)]
@@ -113,24 +224,101 @@ struct ScoreUI;
 #[derive(Component)]
 struct HighScoreUI;
 
-fn setup_score(mut score: ResMut<Score>) {
-    use directories::ProjectDirs;
+fn setup_score(mut score: ResMut<Score>, mut storage: ResMut<Storage>) {
+    match storage.load_scores() {
+        Ok(scores) => score.highscore = scores.highscore,
+        Err(err) => warn!("could not load the high score: {err}"),
+    }
+}
+
+fn save_scores(score: Res<Score>, mut storage: ResMut<Storage>) {
+    if let Err(err) = storage.store(&Scores::new(score.highscore)) {
+        warn!("could not save the high score: {err}");
+    }
+}
+
+/// The game saved last time, if it can be carried on with these options.
+fn load_saved_game(storage: &mut Storage, options: &Options) -> Option<SavedGame> {
+    match storage.load::<SavedGame>() {
+        Ok(saved) => saved.filter(|saved| {
+            saved.grid.width == options.width
+                && saved.grid.height == options.height
+                && saved.grid.has_legal_move()
+        }),
+        Err(err) => {
+            // Logging is not set up yet.
+            eprintln!("ignoring saved game: {err}");
+            None
+        }
+    }
+}
+
+/// The board has changed since the game was last saved.
+#[derive(Resource, Default)]
+struct Unsaved(bool);
+
+fn mark_unsaved(mut unsaved: ResMut<Unsaved>) {
+    unsaved.0 = true;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_game(
+    mut unsaved: ResMut<Unsaved>,
+    grid: Res<Grid>,
+    rng: Res<GameRng>,
+    score: Res<Score>,
+    has_won: Res<HasWon>,
+    stats: Res<GameStats>,
+    recording: Res<Recording>,
+    mut storage: ResMut<Storage>,
+) {
+    if !std::mem::take(&mut unsaved.0) {
+        return;
+    }
+    let saved = SavedGame {
+        hints: stats.hints,
+        imported: score.imported,
+        ..SavedGame::new(
+            grid.clone(),
+            score.current,
+            has_won.0,
+            rng.clone(),
+            stats.moves,
+            recording.clone(),
+        )
+    };
+    if let Err(err) = storage.store(&saved) {
+        warn!("could not save the game: {err}");
+    }
+}
 
-    if let Some(proj_dirs) = ProjectDirs::from("eu", "shadowmitia", "2048") {
-        let highscore = proj_dirs.data_dir().join("highscore.txt");
-        // TODO: robustness
-        if dbg!(highscore.exists()) {
-            score.highscore = std::fs::read_to_string(highscore).unwrap().parse().unwrap();
+/// Keep the recording of a game that just ended with the other replays.
+fn archive_recording(recording: Res<Recording>, mut storage: ResMut<Storage>) {
+    let mut replays = match storage.load::<Replays>() {
+        Ok(replays) => replays.unwrap_or_default(),
+        Err(err) => {
+            warn!("could not load the replays: {err}");
+            Replays::default()
         }
+    };
+    replays.add(recording.clone());
+    if let Err(err) = storage.store(&replays) {
+        warn!("could not save the replays: {err}");
     }
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut grid: ResMut<Grid>) {
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    rng: Res<GameRng>,
+    stats: Res<GameStats>,
+    layout: Res<Layout>,
+) {
     let text_dark: Color = Color::hex("776e65").unwrap();
     let font = asset_server.load("fonts/Kenney Bold.ttf");
     let text_style = TextStyle {
         font: font.clone(),
-        font_size: 60.0,
+        font_size: layout.tile_font_size(),
         color: text_dark,
     };
 
@@ -150,7 +338,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut grid: ResMu
                 flex_basis: Val::Auto,
                 align_content: AlignContent::Stretch,
                 width: Val::Percent(100.0),
-                height: Val::Px(CELL_SIZE.y / 2.0),
+                height: Val::Px(HEADER_HEIGHT),
                 ..Default::default()
             },
             background_color: BackgroundColor(Color::rgba(1.0, 1.0, 1.0, 1.0)),
@@ -179,19 +367,40 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut grid: ResMu
                 ),
                 HighScoreUI,
             ));
+            for (label, button) in [
+                ("Undo", HudButton::History(HistoryRequest::Undo)),
+                ("Redo", HudButton::History(HistoryRequest::Redo)),
+                ("Hint", HudButton::Hint),
+                ("Share", HudButton::Share),
+            ] {
+                builder
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                padding: UiRect::all(Val::Px(10.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: NORMAL_BUTTON.into(),
+                            ..default()
+                        },
+                        button,
+                    ))
+                    .with_children(|builder| {
+                        builder.spawn(TextBundle::from_section(
+                            label,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 28.0,
+                                color: Color::WHITE,
+                            },
+                        ));
+                    });
+            }
         });
 
-    // Place two random tiles
-    for _ in 0..2 {
-        let _ = add_tile(&mut commands, &mut grid, &text_style);
-    }
-}
-
-enum MoveDirection {
-    Left,
-    Right,
-    Up,
-    Down,
+    info!("game with seed {}, {} moves in", rng.seed(), stats.moves);
 }
 
 struct TouchTracking {
@@ -208,13 +417,7 @@ fn input(
     touches: Res<Touches>,
     mut mouse_coords: Local<Vec2>,
     mut current_touch: Local<Option<TouchTracking>>,
-    mut grid: ResMut<Grid>,
-    mut commands: Commands,
-    mut query: Query<(Entity, &mut Cell, &Transform)>,
-    text_style: Res<GameStyle>,
-    mut next_state: ResMut<NextState<AppState>>,
-    mut has_won: ResMut<HasWon>,
-    mut score_events: EventWriter<ScoreEvent>,
+    mut requests: EventWriter<MoveRequest>,
     mut gizmos: Gizmos,
 ) {
     let mut released = false;
@@ -275,10 +478,10 @@ fn input(
     let right_end = -PI / 4.0;
 
     /*
-                    Some(MoveDirection::Left) => Color::GREEN,
-                      Some(MoveDirection::Right) => Color::BLUE,
-                      Some(MoveDirection::Up) => Color::PURPLE,
-    Some(MoveDirection::Down) => Color::ORANGE,
+                    Some(Direction::Left) => Color::GREEN,
+                      Some(Direction::Right) => Color::BLUE,
+                      Some(Direction::Up) => Color::PURPLE,
+    Some(Direction::Down) => Color::ORANGE,
     */
     /*
          gizmos.arc_2d(Vec2::ZERO, up_start, PI / 2.0, 75.0, Color::PURPLE);
@@ -288,14 +491,13 @@ fn input(
     */
 
     let move_direction = {
-        for touch in touches.iter_just_pressed() {
+        // Grab first touch and use that
+        if let Some(touch) = touches.iter_just_pressed().next() {
             *current_touch = Some(TouchTracking {
                 id: Some(touch.id()),
                 start: Some(touch.position()),
                 end: None,
             });
-            // Grab first touch and use that
-            break;
         }
         if let Some(current_touch) = &mut *current_touch {
             for touch in touches.iter_just_released() {
@@ -319,21 +521,21 @@ fn input(
                         if diff.y > 0.0 && diff.x.abs() < diff.y {
                             // TODO: Why flipped on web?
                             if current.id.is_none() {
-                                Some(MoveDirection::Down)
+                                Some(Direction::Down)
                             } else {
-                                Some(MoveDirection::Up)
+                                Some(Direction::Up)
                             }
                         } else if diff.y < 0.0 && diff.x.abs() < -diff.y {
                             // TODO: Why flipped on web?
                             if current.id.is_none() {
-                                Some(MoveDirection::Up)
+                                Some(Direction::Up)
                             } else {
-                                Some(MoveDirection::Down)
+                                Some(Direction::Down)
                             }
                         } else if diff.x < 0.0 && diff.y.abs() < -diff.x {
-                            Some(MoveDirection::Right)
+                            Some(Direction::Right)
                         } else if diff.x > 0.0 && diff.y.abs() < diff.x {
-                            Some(MoveDirection::Left)
+                            Some(Direction::Left)
                         } else {
                             None
                         }
@@ -345,13 +547,13 @@ fn input(
                         if diff.length() > 50.0 {
                             let angle = diff.to_angle();
                             if angle < up_start && angle > up_end {
-                                Some(MoveDirection::Up)
+                                Some(Direction::Up)
                             } else if angle < down_start && angle >= down_end {
-                                Some(MoveDirection::Down)
+                                Some(Direction::Down)
                             } else if angle >= left_start && angle >= left_end {
-                                Some(MoveDirection::Left)
+                                Some(Direction::Left)
                             } else if angle <= right_start && angle <= right_end {
-                                Some(MoveDirection::Right)
+                                Some(Direction::Right)
                             } else {
                                 None
                             }
@@ -374,10 +576,10 @@ fn input(
             }
             if let Some(end) = debug_end {
                 let color = match dir {
-                    Some(MoveDirection::Left) => Color::GREEN,
-                    Some(MoveDirection::Right) => Color::BLUE,
-                    Some(MoveDirection::Up) => Color::PURPLE,
-                    Some(MoveDirection::Down) => Color::ORANGE,
+                    Some(Direction::Left) => Color::GREEN,
+                    Some(Direction::Right) => Color::BLUE,
+                    Some(Direction::Up) => Color::PURPLE,
+                    Some(Direction::Down) => Color::ORANGE,
                     None => Color::RED,
                 };
                 gizmos.circle_2d(end, 50.0, color);
@@ -392,75 +594,250 @@ fn input(
             dir
         } else {
             if input.just_pressed(KeyCode::ArrowLeft) {
-                Some(MoveDirection::Left)
+                Some(Direction::Left)
             } else if input.just_pressed(KeyCode::ArrowRight) {
-                Some(MoveDirection::Right)
+                Some(Direction::Right)
             } else if input.just_pressed(KeyCode::ArrowUp) {
-                Some(MoveDirection::Up)
+                Some(Direction::Up)
             } else if input.just_pressed(KeyCode::ArrowDown) {
-                Some(MoveDirection::Down)
+                Some(Direction::Down)
             } else {
                 None
             }
         }
     };
 
-    if move_direction.is_none() {
-        return;
+    if let Some(direction) = move_direction {
+        requests.send(MoveRequest(direction));
     }
+}
 
-    let (moved, score) = match move_direction.unwrap() {
-        MoveDirection::Left => grid.move_left(),
-        MoveDirection::Right => grid.move_right(),
-        MoveDirection::Up => grid.move_up(),
-        MoveDirection::Down => grid.move_down(),
+/// Play a requested move: slide, record it, then spawn or end the game.
+/// Only one move is played a frame, extra requests are dropped.
+#[allow(clippy::too_many_arguments)]
+fn apply_move(
+    mut requests: EventReader<MoveRequest>,
+    mut grid: ResMut<Grid>,
+    mut rng: ResMut<GameRng>,
+    mut history: ResMut<History>,
+    mut recording: ResMut<Recording>,
+    mut stats: ResMut<GameStats>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut has_won: ResMut<HasWon>,
+    win_condition: Res<WinCondition>,
+    mut score_events: EventWriter<ScoreEvent>,
+    mut moved_events: EventWriter<TilesMoved>,
+) {
+    let request = requests.read().next().copied();
+    requests.clear();
+    let Some(MoveRequest(direction)) = request else {
+        return;
     };
 
-    score_events.send(ScoreEvent(score as u32));
+    let before = grid.clone();
+    let outcome = grid.slide(direction);
+    let changed = outcome.changed;
+
+    score_events.send(ScoreEvent(outcome.score));
+    if changed {
+        // Nothing has been spawned yet, so the generator is still as it was
+        // before the move.
+        history.record(Snapshot {
+            grid: before,
+            rng: rng.clone(),
+            score_delta: outcome.score,
+            has_won: has_won.0,
+        });
+        stats.moves += 1;
+        moved_events.send(TilesMoved(outcome));
+    }
 
     if !grid.has_empty_cells() && !grid.has_legal_move() {
         next_state.set(AppState::GameOver);
         return;
     }
 
-    if let HasWon(false) = *has_won {
-        if grid.max_value() >= 2048 {
-            has_won.0 = true;
-            next_state.set(AppState::Win);
+    let milestone = win_condition.reached(has_won.0, grid.max_value());
+    if let Some(milestone) = milestone {
+        has_won.0 = milestone;
+        next_state.set(AppState::Win);
+    }
+
+    if changed {
+        // The game pauses on the last milestone's win screen without
+        // spawning; earlier ones spawn as usual.
+        if milestone.is_some() && milestone == win_condition.target() {
+            recording.push_win(direction);
             return;
         }
+        let spawned = grid.spawn_tiles(&mut *rng);
+        recording.push(direction, &grid, &spawned);
+        // A spawn can fill the board for good, even right after a milestone.
+        if !grid.has_legal_move() {
+            next_state.set(AppState::GameOver);
+        }
     }
+}
 
-    for (entity, mut cell, trans) in query.iter_mut() {
-        if let Some((from_cell, to_cell)) = moved.iter().find(|(f, _)| f == &cell.coord) {
-            let from = Vec3::new(from_cell.x as f32, from_cell.y as f32, trans.translation.z);
-            let from = grid_coord_to_position(from);
-            let to = Vec3::new(to_cell.x as f32, to_cell.y as f32, trans.translation.z);
-            let to = grid_coord_to_position(to);
-            commands
-                .entity(entity)
-                .insert(tween_translation(0.2, from, to));
+/// Z undoes, Shift+Z or Y redoes; Ctrl is accepted but not required.
+fn history_keys(input: Res<ButtonInput<KeyCode>>, mut requests: EventWriter<HistoryRequest>) {
+    let shift = input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if input.just_pressed(KeyCode::KeyZ) {
+        requests.send(if shift {
+            HistoryRequest::Redo
+        } else {
+            HistoryRequest::Undo
+        });
+    } else if input.just_pressed(KeyCode::KeyY) {
+        requests.send(HistoryRequest::Redo);
+    }
+}
+
+/// Step through the move history. The tiles on screen may be halfway
+/// through an animation, so they are all dropped and rebuilt by
+/// `sync_tiles`.
+#[allow(clippy::too_many_arguments)]
+fn apply_history(
+    mut commands: Commands,
+    mut requests: EventReader<HistoryRequest>,
+    mut history: ResMut<History>,
+    mut grid: ResMut<Grid>,
+    mut rng: ResMut<GameRng>,
+    mut score: ResMut<Score>,
+    mut stats: ResMut<GameStats>,
+    mut recording: ResMut<Recording>,
+    mut has_won: ResMut<HasWon>,
+    tiles: Query<Entity, With<Cell>>,
+) {
+    let mut restored = false;
+    for &request in requests.read() {
+        let current = Snapshot {
+            grid: grid.clone(),
+            rng: rng.clone(),
+            score_delta: 0,
+            has_won: has_won.0,
+        };
+        let snapshot = match request {
+            HistoryRequest::Undo => history.undo(current),
+            HistoryRequest::Redo => history.redo(current),
+        };
+        let Some(snapshot) = snapshot else {
+            continue;
+        };
+
+        match request {
+            HistoryRequest::Undo => {
+                score.current = score.current.saturating_sub(snapshot.score_delta);
+                stats.moves = stats.moves.saturating_sub(1);
+                recording.undo();
+            }
+            HistoryRequest::Redo => {
+                score.current = score.current.saturating_add(snapshot.score_delta);
+                stats.moves += 1;
+                recording.redo();
+            }
+        }
+        *grid = snapshot.grid;
+        *rng = snapshot.rng;
+        has_won.0 = snapshot.has_won;
+        restored = true;
+    }
 
-            cell.coord.x = to_cell.x;
-            cell.coord.y = to_cell.y;
+    if restored {
+        for entity in &tiles {
+            commands.entity(entity).despawn_recursive();
         }
     }
+}
 
-    if !moved.is_empty() {
-        if !add_tile(&mut commands, &mut grid, &text_style.0) && !grid.has_legal_move() {
-            next_state.set(AppState::GameOver);
+/// Animate the tiles of a slide: move them, pop the merged ones and retire
+/// the absorbed ones.
+fn animate_tiles(
+    mut commands: Commands,
+    mut moved_events: EventReader<TilesMoved>,
+    mut query: Query<(Entity, &mut Cell, &Transform)>,
+    layout: Res<Layout>,
+) {
+    for TilesMoved(outcome) in moved_events.read() {
+        for (entity, mut cell, trans) in query.iter_mut() {
+            let origin = cell.coord;
+            if let Some(TileSlide { from, to }) = outcome.slides.iter().find(|s| s.from == origin) {
+                let from = Vec3::new(from.x as f32, from.y as f32, trans.translation.z);
+                let from = grid_coord_to_position(&layout, from);
+                let to_position = Vec3::new(to.x as f32, to.y as f32, trans.translation.z);
+                let to_position = grid_coord_to_position(&layout, to_position);
+                commands
+                    .entity(entity)
+                    .insert(tween_translation(0.2, from, to_position));
+
+                cell.coord = *to;
+            }
+            // The absorbed tile stops being a board tile and goes away once it
+            // has slid into the one it merged with.
+            if outcome.merges.iter().any(|m| m.absorbed == origin) {
+                commands
+                    .entity(entity)
+                    .remove::<Cell>()
+                    .insert(DespawnAfterTween);
+            }
+            // Make the tile that absorbed another one pop.
+            if outcome.merges.iter().any(|m| m.survivor == origin) {
+                commands.entity(entity).insert(tween_scale(
+                    0.2,
+                    Vec3::new(1.2, 1.2, 1.0),
+                    Vec3::ONE,
+                ));
+            }
+        }
+    }
+}
+
+/// Make the tile entities match the `Grid`, whatever changed it: despawn
+/// tiles on empty or out of range cells and spawn the missing ones.
+fn sync_tiles(
+    mut commands: Commands,
+    grid: Res<Grid>,
+    layout: Res<Layout>,
+    text_style: Res<GameStyle>,
+    query: Query<(Entity, &Cell)>,
+) {
+    if !grid.is_changed() {
+        return;
+    }
+
+    let mut occupied = vec![false; grid.cells.len()];
+    for (entity, cell) in query.iter() {
+        let UVec2 { x, y } = cell.coord;
+        let (x, y) = (x as usize, y as usize);
+        if x >= grid.width || y >= grid.height {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let index = Grid::index_2d(x, y, grid.width, grid.height);
+        if grid.cells[index] == 0 || occupied[index] {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            occupied[index] = true;
+        }
+    }
+
+    for (index, &rank) in grid.cells.iter().enumerate() {
+        if rank != 0 && !occupied[index] {
+            let coord = Grid::index_to_coord(index, grid.width, grid.height);
+            spawn_tile(&mut commands, &layout, &text_style.0, coord, rank);
         }
     }
 }
 
 fn add_score(
+    state: Res<State<AppState>>,
     mut ev_score: EventReader<ScoreEvent>,
     mut score: ResMut<Score>,
     mut score_ui: Query<&mut Text, With<ScoreUI>>,
     mut high_score_ui: Query<&mut Text, (With<HighScoreUI>, Without<ScoreUI>)>,
 ) {
     for ev in ev_score.read() {
-        score.current += ev.0;
+        score.current = score.current.saturating_add(ev.0);
     }
 
     if let Ok(mut score_ui) = score_ui.get_single_mut() {
@@ -468,18 +845,14 @@ fn add_score(
 
         //
         {
-            if score.current > score.highscore {
+            // Replays were counted when they were played.
+            let own = !score.imported && *state.get() != AppState::Replay;
+            if own && score.current > score.highscore {
                 score.highscore = score.current;
             }
 
-            let mut score_ui = high_score_ui.get_single_mut().unwrap();
-            score_ui.sections[0].value = format!("High score {}", score.highscore);
-
-            use directories::ProjectDirs;
-
-            if let Some(proj_dirs) = ProjectDirs::from("eu", "shadowmitia", "2048") {
-                let highscore = proj_dirs.data_dir().join("highscore.txt");
-                std::fs::write(highscore, score.highscore.to_string());
+            if let Ok(mut score_ui) = high_score_ui.get_single_mut() {
+                score_ui.sections[0].value = format!("High score {}", score.highscore);
             }
         }
     }
@@ -487,38 +860,44 @@ fn add_score(
 
 fn update_tile_graphics(
     grid: Res<Grid>,
-    mut query: Query<(Ref<Cell>, &mut Sprite)>,
-    mut text_query: Query<(Ref<Cell>, &mut Text)>,
+    mut query: Query<(&Cell, &mut Sprite, &Children)>,
+    mut text_query: Query<&mut Text>,
 ) {
-    for (c, mut s) in query.iter_mut() {
-        let score = grid.cells[Grid::index_2d(c.coord.x as usize, c.coord.y as usize, 4, 4)] as u32;
-        s.color = score_to_colour(score);
-    }
-
-    for (cell, mut text) in text_query.iter_mut() {
-        let score =
-            grid.cells[Grid::index_2d(cell.coord.x as usize, cell.coord.y as usize, 4, 4)] as u32;
+    for (c, mut s, children) in query.iter_mut() {
+        let rank = grid.rank_at(c.coord);
+        s.color = rank_to_colour(rank);
 
-        text.sections[0].value = score.to_string();
+        let mut texts = text_query.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            text.sections[0].value = rank_to_value(rank).to_string();
+        }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn reset_game(
-    mut commands: Commands,
     mut grid: ResMut<Grid>,
+    mut rng: ResMut<GameRng>,
+    options: Res<Options>,
     mut has_won: ResMut<HasWon>,
-    text_style: Res<GameStyle>,
     mut score: ResMut<Score>,
+    mut history: ResMut<History>,
+    mut stats: ResMut<GameStats>,
+    mut recording: ResMut<Recording>,
 ) {
-    *grid = Grid::new();
+    *grid = Grid::with_size(grid.width, grid.height).with_rules(grid.rules.clone());
+    *rng = GameRng::new(options.seed);
+    info!("new game with seed {}", rng.seed());
 
-    for _ in 0..2 {
-        let _ = add_tile(&mut commands, &mut grid, &text_style.0);
-    }
+    let start = grid.start(&mut *rng);
+    *recording = Recording::new(&grid, rng.seed(), &start);
 
-    *has_won = HasWon(false);
+    *has_won = HasWon(0);
+    history.clear();
+    *stats = GameStats::default();
 
     score.current = 0;
+    score.imported = false;
 }
 
 fn button_system(
@@ -526,7 +905,13 @@ fn button_system(
     text_style: Res<GameStyle>,
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor),
-        (Changed<Interaction>, With<Button>),
+        (
+            Changed<Interaction>,
+            With<Button>,
+            Without<HudButton>,
+            Without<replay::ReplayButton>,
+            Without<share_dialog::ShareButton>,
+        ),
     >,
 ) {
     for (interaction, mut color) in &mut interaction_query {
@@ -545,6 +930,44 @@ fn button_system(
     }
 }
 
+fn hud_button_system(
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut requests: EventWriter<HistoryRequest>,
+    mut hint_requests: EventWriter<hint::HintRequest>,
+    mut interaction_query: Query<
+        (&Interaction, &HudButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+) {
+    for (interaction, button, mut color) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                match *button {
+                    HudButton::History(request) => {
+                        requests.send(request);
+                    }
+                    HudButton::Hint => {
+                        hint_requests.send(hint::HintRequest);
+                    }
+                    // Leaving the game over or win screen would start a new game.
+                    HudButton::Share if *state.get() == AppState::InGame => {
+                        next_state.set(AppState::Share);
+                    }
+                    HudButton::Share => {}
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
 #[derive(Component)]
 struct GameOverUI;
 
@@ -614,7 +1037,7 @@ fn game_over(mut commands: Commands, font: Res<GameFont>) {
 #[derive(Component)]
 struct WinUI;
 
-fn win_screen(mut commands: Commands, font: Res<GameFont>) {
+fn win_screen(mut commands: Commands, font: Res<GameFont>, has_won: Res<HasWon>) {
     let font = &font.0;
 
     commands
@@ -638,7 +1061,7 @@ fn win_screen(mut commands: Commands, font: Res<GameFont>) {
         ))
         .with_children(|builder| {
             builder.spawn(TextBundle::from_section(
-                "You got 2048!",
+                format!("You got {}!", has_won.0),
                 TextStyle {
                     font: font.clone(),
                     font_size: 42.0,
@@ -713,29 +1136,89 @@ fn cleanup_system<T: Component>(mut commands: Commands, query: Query<Entity, Wit
 }
 
 fn main() {
+    let options = Options::from_args();
+    let layout = Layout::new(options.width, options.height);
+
+    let mut storage = Storage::open();
+    let win_condition = WinCondition {
+        milestones: options.milestones.clone(),
+    };
+    let saved = if let Some(position) = options.position.clone() {
+        Some(share_dialog::game_from_position(
+            position,
+            options.difficulty.clone(),
+            &win_condition,
+        ))
+    } else if options.new_game {
+        None
+    } else {
+        load_saved_game(&mut storage, &options)
+    };
+    let saved = saved.unwrap_or_else(|| {
+        let mut rng = GameRng::new(options.seed);
+        let mut grid =
+            Grid::with_size(options.width, options.height).with_rules(options.difficulty.clone());
+        let start = grid.start(&mut rng);
+        let recording = Recording::new(&grid, rng.seed(), &start);
+        SavedGame::new(grid, 0, 0, rng, 0, recording)
+    });
+
     App::new()
         .init_state::<AppState>()
-        .init_resource::<Grid>()
-        .init_resource::<HasWon>()
-        .init_resource::<Score>()
+        .insert_resource(saved.grid)
+        .insert_resource(saved.rng)
+        .insert_resource(Score {
+            current: saved.score,
+            highscore: 0,
+            imported: saved.imported,
+        })
+        .insert_resource(HasWon(saved.has_won))
+        .insert_resource(saved.recording)
+        .insert_resource(storage)
+        .insert_resource(GameStats {
+            moves: saved.moves,
+            hints: saved.hints,
+        })
+        .insert_resource(History::with_limit(options.undo_limit))
+        .init_resource::<Unsaved>()
+        .insert_resource(win_condition)
+        .insert_resource(layout)
+        .insert_resource(options)
         .add_event::<ScoreEvent>()
+        .add_event::<MoveRequest>()
+        .add_event::<TilesMoved>()
+        .add_event::<HistoryRequest>()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "ShadowMitia's 2048".into(),
-                resolution: (WINDOW_SIZE.x, WINDOW_SIZE.y).into(),
+                resolution: (layout.window_size.x, layout.window_size.y).into(),
                 ..default()
             }),
             ..default()
         }))
         .add_systems(Startup, (setup_score, setup))
         .add_systems(Update, bevy::window::close_on_esc)
-        .add_systems(Update, add_score)
+        .add_systems(Update, add_score.after(apply_move))
         .add_systems(Update, (tween_scale_system, tween_translation_system))
         .add_systems(
             Update,
-            (update_tile_graphics, input).run_if(in_state(AppState::InGame)),
+            (input, apply_move, history_keys, apply_history)
+                .chain()
+                .before(animate_tiles)
+                .run_if(in_state(AppState::InGame)),
         )
-        .add_systems(OnEnter(AppState::GameOver), game_over)
+        .add_systems(
+            Update,
+            (update_tile_graphics, (animate_tiles, sync_tiles).chain())
+                .run_if(in_state(AppState::InGame).or_else(in_state(AppState::Replay))),
+        )
+        .add_plugins((
+            replay::plugin,
+            share_dialog::plugin,
+            hint::plugin,
+            autoplay::plugin,
+        ))
+        .add_systems(OnEnter(AppState::GameOver), (game_over, archive_recording))
         .add_systems(
             OnExit(AppState::GameOver),
             (
@@ -746,17 +1229,48 @@ fn main() {
         )
         .add_systems(OnEnter(AppState::Win), (win_screen,))
         .add_systems(OnExit(AppState::Win), cleanup_system::<WinUI>)
-        .add_systems(Update, button_system)
+        .add_systems(Update, (button_system, hud_button_system))
+        .add_systems(
+            Update,
+            (
+                mark_unsaved.run_if(resource_changed::<Grid>),
+                save_game.run_if(on_timer(SAVE_INTERVAL)),
+            )
+                .chain()
+                .after(add_score)
+                .after(sync_tiles)
+                .run_if(not(in_state(AppState::Replay))),
+        )
+        .add_systems(OnExit(AppState::InGame), save_game)
+        .add_systems(
+            Update,
+            save_scores
+                .after(add_score)
+                .run_if(resource_changed::<Score>),
+        )
+        .add_systems(
+            Last,
+            (
+                save_game.run_if(not(in_state(AppState::Replay))),
+                save_scores,
+            )
+                .run_if(on_event::<AppExit>()),
+        )
         .run();
 }
 
 fn tween_translation_system(
     mut commands: Commands,
-    mut query: Query<(Entity, &mut TweenTranslation, &mut Transform)>,
+    mut query: Query<(
+        Entity,
+        &mut TweenTranslation,
+        &mut Transform,
+        Has<DespawnAfterTween>,
+    )>,
     time: Res<Time>,
 ) {
     let time = time.elapsed_seconds();
-    for (entity, mut tween, mut trans) in query.iter_mut() {
+    for (entity, mut tween, mut trans, despawn) in query.iter_mut() {
         if tween.completed {
             continue;
         }
@@ -776,7 +1290,11 @@ fn tween_translation_system(
         }
 
         if tween.completed {
-            commands.entity(entity).remove::<TweenTranslation>();
+            if despawn {
+                commands.entity(entity).despawn_recursive();
+            } else {
+                commands.entity(entity).remove::<TweenTranslation>();
+            }
         }
     }
 }
@@ -0,0 +1,491 @@
+//! Text notation for positions and games, to paste into bug reports and
+//! tests.
+//!
+//! A position lists the rows from the top of the board down, separated by
+//! `/`, one character per cell giving the tile's exponent: `0` for an empty
+//! cell, `1` for a 2, up to `9` then `a` (1024), `b` (2048) and on to `z`.
+//! Larger exponents are written in brackets, e.g. `[36]`.
+//!
+//! ```text
+//! 0000/0100/0021/b321
+//! ```
+//!
+//! is a 4x4 board with a 2048 in the bottom left corner.
+//!
+//! A game is a [`Recording`] written as lines of `key value`:
+//!
+//! ```text
+//! size 4x4
+//! rules 2:0.9 4:0.1 per-move 1 starting 2
+//! seed 2048
+//! start b1=2 d3=2
+//! moves Lc4=4 Ua1=2 D
+//! ```
+//!
+//! Cells are named like on a chess board: a letter for the column from `a`
+//! on the left, and a number for the row from `1` at the bottom. Each move
+//! is `L`, `R`, `U` or `D` followed by the tiles spawned after it, separated
+//! by commas. The `moves` list may carry on over the following lines.
+//!
+//! The move that won the game, which spawned nothing, is marked with `!`,
+//! e.g. `U!`. A game carried on from a shared position has a `position` line
+//! with the score it started on, e.g. `position 1200`; its `start` then
+//! lists every tile.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::grid::{value_to_rank, Direction, Grid, SpawnRules, MAX_SIZE, MIN_SIZE};
+use crate::recording::{RecordedMove, Recording, Spawn};
+
+/// Largest exponent a position spells with a single character.
+const MAX_RANK: u8 = 35;
+
+/// Largest exponent of a tile that fits in a `u64`.
+const MAX_BRACKETED_RANK: u8 = 63;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotationError(pub String);
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+fn error(message: impl Into<String>) -> NotationError {
+    NotationError(message.into())
+}
+
+fn rank_to_char(rank: u8) -> Option<char> {
+    char::from_digit(rank as u32, MAX_RANK as u32 + 1)
+}
+
+fn char_to_rank(c: char) -> Option<u8> {
+    c.to_digit(MAX_RANK as u32 + 1).map(|rank| rank as u8)
+}
+
+/// The ranks of one row of a position.
+fn parse_row(row: &str) -> Result<Vec<u8>, NotationError> {
+    let mut ranks = Vec::new();
+    let mut chars = row.trim().chars();
+    while let Some(c) = chars.next() {
+        let rank = if c == '[' {
+            let rest = chars.as_str();
+            let (rank, after) = rest
+                .split_once(']')
+                .ok_or_else(|| error(format!("unclosed '[' in '{row}'")))?;
+            chars = after.chars();
+            rank.parse()
+                .ok()
+                .filter(|&rank| rank <= MAX_BRACKETED_RANK)
+                .ok_or_else(|| error(format!("bad cell '[{rank}]'")))?
+        } else {
+            char_to_rank(c).ok_or_else(|| error(format!("bad cell '{c}'")))?
+        };
+        ranks.push(rank);
+    }
+    Ok(ranks)
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for j in (0..self.height).rev() {
+            for i in 0..self.width {
+                let rank = self.cells[Grid::index_2d(i, j, self.width, self.height)];
+                match rank_to_char(rank) {
+                    Some(c) => write!(f, "{c}")?,
+                    None => write!(f, "[{rank}]")?,
+                }
+            }
+            if j > 0 {
+                f.write_str("/")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a position with the default spawn rules.
+impl FromStr for Grid {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rows = s
+            .trim()
+            .split('/')
+            .map(parse_row)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let height = rows.len();
+        let width = rows[0].len();
+        if width < 2 || height < 2 {
+            return Err(error("a board needs at least 2 cells in each direction"));
+        }
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(error("rows of different lengths"));
+        }
+
+        let mut grid = Grid::with_size(width, height);
+        for (k, row) in rows.iter().enumerate() {
+            let j = height - 1 - k;
+            for (i, &rank) in row.iter().enumerate() {
+                grid.cells[Grid::index_2d(i, j, width, height)] = rank;
+            }
+        }
+        Ok(grid)
+    }
+}
+
+fn direction_letter(direction: Direction) -> char {
+    match direction {
+        Direction::Left => 'L',
+        Direction::Right => 'R',
+        Direction::Up => 'U',
+        Direction::Down => 'D',
+    }
+}
+
+fn parse_direction(c: char) -> Option<Direction> {
+    match c {
+        'L' => Some(Direction::Left),
+        'R' => Some(Direction::Right),
+        'U' => Some(Direction::Up),
+        'D' => Some(Direction::Down),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Spawn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let column = char::from_u32('a' as u32 + self.x).unwrap_or('?');
+        write!(f, "{column}{}={}", self.y + 1, self.value)
+    }
+}
+
+impl FromStr for Spawn {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || error(format!("bad spawn '{s}'"));
+        let (cell, value) = s.split_once('=').ok_or_else(invalid)?;
+        let mut chars = cell.chars();
+        let column = chars
+            .next()
+            .filter(char::is_ascii_lowercase)
+            .ok_or_else(invalid)?;
+        let row: u32 = chars.as_str().parse().map_err(|_| invalid())?;
+        let value = value.parse().map_err(|_| invalid())?;
+        if row == 0 || value_to_rank(value).unwrap_or(0) == 0 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            x: column as u32 - 'a' as u32,
+            y: row - 1,
+            value,
+        })
+    }
+}
+
+fn write_spawns(f: &mut fmt::Formatter<'_>, spawns: &[Spawn]) -> fmt::Result {
+    for (n, spawn) in spawns.iter().enumerate() {
+        if n > 0 {
+            f.write_str(",")?;
+        }
+        write!(f, "{spawn}")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for RecordedMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", direction_letter(self.direction))?;
+        if self.won {
+            f.write_str("!")?;
+        }
+        write_spawns(f, &self.spawns)
+    }
+}
+
+impl FromStr for RecordedMove {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let direction = chars
+            .next()
+            .and_then(parse_direction)
+            .ok_or_else(|| error(format!("bad move '{s}'")))?;
+        let rest = chars.as_str();
+        let (won, rest) = match rest.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let spawns = match rest {
+            "" => Vec::new(),
+            spawns => spawns
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(Self {
+            direction,
+            spawns,
+            won,
+        })
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "size {}x{}", self.width, self.height)?;
+        f.write_str("rules")?;
+        for (value, weight) in &self.rules.values {
+            write!(f, " {value}:{weight}")?;
+        }
+        writeln!(
+            f,
+            " per-move {} starting {}",
+            self.rules.per_move, self.rules.starting
+        )?;
+        writeln!(f, "seed {}", self.seed)?;
+        if self.from_position {
+            writeln!(f, "position {}", self.score)?;
+        }
+        f.write_str("start ")?;
+        for (n, spawn) in self.start.iter().enumerate() {
+            if n > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{spawn}")?;
+        }
+        f.write_str("\nmoves")?;
+        for recorded in &self.moves {
+            write!(f, " {recorded}")?;
+        }
+        writeln!(f)
+    }
+}
+
+fn parse_rules(s: &str) -> Result<SpawnRules, NotationError> {
+    let mut rules = SpawnRules {
+        values: Vec::new(),
+        per_move: 1,
+        starting: 2,
+    };
+    let mut words = s.split_whitespace();
+    while let Some(word) = words.next() {
+        let mut count = || {
+            words
+                .next()
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(|| error(format!("missing count after {word}")))
+        };
+        match word {
+            "per-move" => rules.per_move = count()?,
+            "starting" => rules.starting = count()?,
+            _ => {
+                let invalid = || error(format!("bad spawn weight '{word}'"));
+                let (value, weight) = word.split_once(':').ok_or_else(invalid)?;
+                let value = value.parse().map_err(|_| invalid())?;
+                if value_to_rank(value).unwrap_or(0) == 0 {
+                    return Err(invalid());
+                }
+                let weight = weight.parse().map_err(|_| invalid())?;
+                rules.values.push((value, weight));
+            }
+        }
+    }
+    if rules.values.is_empty() {
+        return Err(error("rules without tile values"));
+    }
+    Ok(rules)
+}
+
+impl FromStr for Recording {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut size, mut rules, mut seed, mut start) = (None, None, None, None);
+        let (mut position, mut moves) = (None, None);
+        let mut lines = s.lines();
+        while let Some(line) = lines.next() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "size" => {
+                    let invalid = || error(format!("bad size '{rest}'"));
+                    let (width, height) = rest.trim().split_once('x').ok_or_else(invalid)?;
+                    let width: usize = width.parse().map_err(|_| invalid())?;
+                    let height: usize = height.parse().map_err(|_| invalid())?;
+                    let extents = MIN_SIZE..=MAX_SIZE;
+                    if !extents.contains(&width) || !extents.contains(&height) {
+                        return Err(error(format!(
+                            "board dimensions must be between {MIN_SIZE} and {MAX_SIZE}"
+                        )));
+                    }
+                    size = Some((width, height));
+                }
+                "rules" => rules = Some(parse_rules(rest)?),
+                "seed" => {
+                    seed = Some(
+                        rest.trim()
+                            .parse()
+                            .map_err(|_| error(format!("bad seed '{rest}'")))?,
+                    );
+                }
+                "position" => {
+                    position = Some(
+                        rest.trim()
+                            .parse()
+                            .map_err(|_| error(format!("bad score '{rest}'")))?,
+                    );
+                }
+                "start" => {
+                    start = Some(
+                        rest.split_whitespace()
+                            .map(str::parse)
+                            .collect::<Result<Vec<Spawn>, _>>()?,
+                    );
+                }
+                "moves" => {
+                    // Everything left is moves.
+                    let rest = std::iter::once(rest).chain(lines.by_ref());
+                    moves = Some(
+                        rest.flat_map(str::split_whitespace)
+                            .map(str::parse)
+                            .collect::<Result<Vec<RecordedMove>, _>>()?,
+                    );
+                }
+                _ => return Err(error(format!("unknown line '{line}'"))),
+            }
+        }
+
+        let (width, height) = size.ok_or_else(|| error("missing size"))?;
+        let mut grid = Grid::with_size(width, height);
+        grid.rules = rules.unwrap_or_default();
+        let mut recording = Recording::new(&grid, seed.ok_or_else(|| error("missing seed"))?, &[]);
+        recording.start = start.ok_or_else(|| error("missing start"))?;
+        if let Some(score) = position {
+            recording.from_position = true;
+            recording.score = score;
+        }
+        recording.moves = moves.unwrap_or_default();
+        recording
+            .position(recording.moves.len())
+            .map_err(|err| error(err.to_string()))?;
+        Ok(recording)
+    }
+}
+
+#[cfg(test)]
+mod notation_tests {
+    use super::*;
+    use crate::rng::GameRng;
+
+    #[test]
+    fn positions_read_top_row_first() {
+        let grid: Grid = "0000/0100/0021/b321".parse().unwrap();
+
+        assert_eq!((grid.width, grid.height), (4, 4));
+        #[rustfmt::skip]
+        assert_eq!(grid.values(), [2048, 8, 4, 2,
+                                   0, 0, 4, 2,
+                                   0, 2, 0, 0,
+                                   0, 0, 0, 0]);
+        assert_eq!(grid.to_string(), "0000/0100/0021/b321");
+    }
+
+    #[test]
+    fn positions_of_any_size_round_trip() {
+        for text in ["00/01", "01234/56789/abcde", "z0/00/00/0f"] {
+            assert_eq!(text.parse::<Grid>().unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn tiles_past_z_are_bracketed() {
+        let mut grid: Grid = "z0/00".parse().unwrap();
+        grid.cells[0] = MAX_RANK + 1;
+        grid.cells[3] = MAX_BRACKETED_RANK;
+        let text = grid.to_string();
+        assert_eq!(text, "z[63]/[36]0");
+        assert_eq!(text.parse::<Grid>().unwrap().cells, grid.cells);
+
+        for text in ["[64]0/00", "[30/00", "[]0/00", "[x]0/00", "[1]]/00"] {
+            assert!(text.parse::<Grid>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn bad_positions_are_refused() {
+        for text in ["", "0", "00/0", "000/0-0", "0/0"] {
+            assert!(text.parse::<Grid>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn slides_written_as_positions() {
+        let mut grid: Grid = "2211/0000/1100/0011".parse().unwrap();
+        let _ = grid.slide(Direction::Left);
+        assert_eq!(grid.to_string(), "3200/0000/2000/2000");
+    }
+
+    #[test]
+    fn games_round_trip() {
+        let mut rng = GameRng::from_seed(2048);
+        let mut grid = Grid::new().with_rules(SpawnRules::hard());
+        let start = grid.start(&mut rng);
+        let mut recording = Recording::new(&grid, 2048, &start);
+        for direction in [Direction::Left, Direction::Down, Direction::Right] {
+            if grid.slide(direction).changed {
+                let spawned = grid.spawn_tiles(&mut rng);
+                recording.push(direction, &grid, &spawned);
+            }
+        }
+
+        let text = recording.to_string();
+        assert!(text.starts_with("size 4x4\nrules 2:0.7 4:0.25 8:0.05 per-move 1 starting 3\n"));
+        let parsed: Recording = text.parse().unwrap();
+        assert_eq!(parsed, recording);
+        assert!(parsed.verify().is_ok());
+
+        let carried_on = Recording::from_position(&grid, 5, 1200);
+        let text = carried_on.to_string();
+        assert!(text.contains("\nposition 1200\n"));
+        assert_eq!(text.parse::<Recording>().unwrap(), carried_on);
+    }
+
+    #[test]
+    fn games_in_hand_written_notation() {
+        let text = "
+            size 3x2
+            seed 1
+            start a1=2 c1=2
+            moves La2=4
+                  R!
+        ";
+        let recording: Recording = text.parse().unwrap();
+        assert_eq!(recording.rules, SpawnRules::default());
+        assert_eq!(recording.moves.len(), 2);
+        assert_eq!(recording.moves[0].to_string(), "La2=4");
+        assert!(recording.moves[1].won);
+        assert_eq!(recording.moves[1].to_string(), "R!");
+        assert_eq!(recording.position(2).unwrap().0.to_string(), "002/002");
+    }
+
+    #[test]
+    fn games_on_boards_too_large_are_refused() {
+        for size in ["1x4", "17x4", "100000x100000", "99999999999999999999x2"] {
+            let text = format!("size {size}\nseed 1\nstart a1=2\nmoves");
+            assert!(text.parse::<Recording>().is_err(), "{size}");
+        }
+        let text = "size 16x2\nseed 1\nstart a1=2\nmoves";
+        assert!(text.parse::<Recording>().is_ok());
+    }
+}
@@ -0,0 +1,242 @@
+use bevy::prelude::*;
+
+use shadowmitia_2048::grid::{SpawnRules, DEFAULT_SIZE, MAX_SIZE, MIN_SIZE};
+use shadowmitia_2048::share::{self, SharedPosition};
+use shadowmitia_2048::strategy::StrategyKind;
+
+const USAGE: &str = "usage: shadowmitia_2048 [--size N | --size WxH] [--seed SEED] \
+                     [--difficulty easy|normal|hard] \
+                     [--target N | --milestones N,N,... | --endless] \
+                     [--undo-limit N] [--new | --load CODE] \
+                     [--strategy random|greedy|corner|solver] [--autoplay-rate N]";
+
+#[derive(Resource, Debug, Clone)]
+pub struct Options {
+    pub width: usize,
+    pub height: usize,
+    /// Replay the same spawns every game instead of picking a fresh seed.
+    pub seed: Option<u64>,
+    pub difficulty: SpawnRules,
+    /// Tile values that show the win screen; empty for endless games.
+    pub milestones: Vec<u64>,
+    /// Undos allowed per game, `None` for no limit.
+    pub undo_limit: Option<usize>,
+    /// Start a new game instead of resuming the saved one.
+    pub new_game: bool,
+    /// Carry on from a shared position instead; it sets the board size.
+    pub position: Option<SharedPosition>,
+    /// How the game plays itself when autoplay is on.
+    pub strategy: StrategyKind,
+    /// Moves per second played by autoplay.
+    pub autoplay_rate: f32,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_SIZE,
+            height: DEFAULT_SIZE,
+            seed: None,
+            difficulty: SpawnRules::default(),
+            milestones: vec![2048],
+            undo_limit: None,
+            new_game: false,
+            position: None,
+            strategy: StrategyKind::default(),
+            autoplay_rate: 4.0,
+        }
+    }
+}
+
+impl Options {
+    /// Parse the process arguments, exiting with a usage message on error.
+    #[must_use]
+    pub fn from_args() -> Self {
+        match Self::parse(std::env::args().skip(1)) {
+            Ok(options) => options,
+            Err(err) => {
+                eprintln!("{err}\n{USAGE}");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for {flag}"))
+            };
+
+            match flag.as_str() {
+                "--size" => {
+                    (options.width, options.height) = parse_size(&value()?)?;
+                }
+                "--seed" => {
+                    let value = value()?;
+                    options.seed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid seed '{value}'"))?,
+                    );
+                }
+                "--difficulty" => {
+                    options.difficulty = match value()?.as_str() {
+                        "easy" => SpawnRules::easy(),
+                        "normal" => SpawnRules::normal(),
+                        "hard" => SpawnRules::hard(),
+                        other => return Err(format!("unknown difficulty '{other}'")),
+                    };
+                }
+                "--target" => {
+                    options.milestones = vec![parse_tile(&value()?)?];
+                }
+                "--milestones" => {
+                    options.milestones = value()?
+                        .split(',')
+                        .map(parse_tile)
+                        .collect::<Result<_, _>>()?;
+                }
+                "--endless" => options.milestones.clear(),
+                "--undo-limit" => {
+                    let value = value()?;
+                    options.undo_limit = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid undo limit '{value}'"))?,
+                    );
+                }
+                "--new" => options.new_game = true,
+                "--load" => {
+                    let position = share::decode(&value()?)
+                        .map_err(|err| format!("cannot load the position: {err}"))?;
+                    check_size(position.grid.width, position.grid.height)?;
+                    options.position = Some(position);
+                }
+                "--strategy" => options.strategy = value()?.parse()?,
+                "--autoplay-rate" => {
+                    let value = value()?;
+                    options.autoplay_rate = match value.parse::<f32>() {
+                        Ok(rate) if rate > 0.0 && rate.is_finite() => rate,
+                        _ => return Err(format!("invalid autoplay rate '{value}'")),
+                    };
+                }
+                "--help" | "-h" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                _ => return Err(format!("unknown argument '{flag}'")),
+            }
+        }
+
+        if let Some(position) = &options.position {
+            (options.width, options.height) = (position.grid.width, position.grid.height);
+        }
+        Ok(options)
+    }
+}
+
+/// Accepts either `N` for a square board or `WxH`.
+fn parse_size(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("invalid board size '{value}'");
+    let (width, height) = match value.split_once(['x', 'X']) {
+        Some((width, height)) => (
+            width.parse().map_err(|_| invalid())?,
+            height.parse().map_err(|_| invalid())?,
+        ),
+        None => {
+            let size = value.parse().map_err(|_| invalid())?;
+            (size, size)
+        }
+    };
+
+    check_size(width, height)?;
+    Ok((width, height))
+}
+
+fn check_size(width: usize, height: usize) -> Result<(), String> {
+    for extent in [width, height] {
+        if !(MIN_SIZE..=MAX_SIZE).contains(&extent) {
+            return Err(format!(
+                "board dimensions must be between {MIN_SIZE} and {MAX_SIZE}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// A tile value, i.e. a power of two of at least 4.
+fn parse_tile(value: &str) -> Result<u64, String> {
+    match value.trim().parse::<u64>() {
+        Ok(tile) if tile >= 4 && tile.is_power_of_two() => Ok(tile),
+        _ => Err(format!("'{value}' is not a tile value")),
+    }
+}
+
+#[cfg(test)]
+mod options_tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn sizes_are_square_or_rectangular() {
+        let options = parse(&["--size", "5"]).unwrap();
+        assert_eq!((options.width, options.height), (5, 5));
+        let options = parse(&["--size=3x7"]).unwrap();
+        assert_eq!((options.width, options.height), (3, 7));
+        assert_eq!(parse_size("6X2"), Ok((6, 2)));
+    }
+
+    #[test]
+    fn bad_sizes_are_refused() {
+        for size in ["", "x", "4x", "x4", "4x4x4", "-3", "four", "4.5"] {
+            assert!(parse_size(size).is_err(), "{size}");
+        }
+        for size in ["1", "17", "4x1", "2x17", "0x0", "99999999999999999999"] {
+            assert!(parse_size(size).is_err(), "{size}");
+        }
+        assert_eq!(parse_size("2x16"), Ok((2, 16)));
+    }
+
+    #[test]
+    fn unknown_flags_and_missing_values_are_refused() {
+        assert_eq!(
+            parse(&["--colour", "red"]).unwrap_err(),
+            "unknown argument '--colour'"
+        );
+        assert_eq!(parse(&["--size"]).unwrap_err(), "missing value for --size");
+        assert_eq!(
+            parse(&["--new", "--seed"]).unwrap_err(),
+            "missing value for --seed"
+        );
+        assert!(parse(&["--seed", "soon"]).is_err());
+        assert!(parse(&["--target", "3"]).is_err());
+        assert!(parse(&["--difficulty", "brutal"]).is_err());
+    }
+
+    #[test]
+    fn defaults_and_flags() {
+        let options = parse(&[]).unwrap();
+        assert_eq!(options.milestones, [2048]);
+        assert!(!options.new_game);
+
+        let options = parse(&["--endless", "--new", "--undo-limit=3", "--seed", "9"]).unwrap();
+        assert!(options.milestones.is_empty());
+        assert!(options.new_game);
+        assert_eq!((options.undo_limit, options.seed), (Some(3), Some(9)));
+        let options = parse(&["--milestones", "512,2048"]).unwrap();
+        assert_eq!(options.milestones, [512, 2048]);
+    }
+}
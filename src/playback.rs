@@ -0,0 +1,146 @@
+//! The clock of a replay: which move is on screen, and when to show the next.
+
+/// Moves per second a replay can be played at.
+pub const MIN_SPEED: f32 = 0.25;
+pub const MAX_SPEED: f32 = 16.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playback {
+    /// Moves played so far, from 0 (the starting board) to `len`.
+    pub step: usize,
+    /// Moves in the recording.
+    pub len: usize,
+    pub playing: bool,
+    /// Moves per second.
+    pub speed: f32,
+    /// Time since the last step while playing.
+    elapsed: f32,
+}
+
+impl Playback {
+    /// Playing from the start at two moves a second.
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        Self {
+            step: 0,
+            len,
+            playing: true,
+            speed: 2.0,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Let `seconds` pass, returning the step to show if it changed. Stops
+    /// playing at the end.
+    pub fn tick(&mut self, seconds: f32) -> Option<usize> {
+        if !self.playing {
+            return None;
+        }
+        if self.step >= self.len {
+            self.playing = false;
+            return None;
+        }
+        self.elapsed += seconds;
+        let period = 1.0 / self.speed;
+        let steps = (self.elapsed / period) as usize;
+        if steps == 0 {
+            return None;
+        }
+        self.elapsed -= steps as f32 * period;
+        self.seek(self.step + steps)
+    }
+
+    /// Jump to `step`, clamped to the recording. Returns it if it changed.
+    pub fn seek(&mut self, step: usize) -> Option<usize> {
+        let step = step.min(self.len);
+        if step == self.step {
+            return None;
+        }
+        self.step = step;
+        if step == self.len {
+            self.playing = false;
+        }
+        Some(step)
+    }
+
+    /// One move forward or back, pausing playback.
+    pub fn step_by(&mut self, delta: isize) -> Option<usize> {
+        self.playing = false;
+        self.seek(self.step.saturating_add_signed(delta))
+    }
+
+    /// Play or pause; playing from the end starts over.
+    pub fn toggle(&mut self) -> Option<usize> {
+        self.playing = !self.playing;
+        self.elapsed = 0.0;
+        if self.playing && self.step == self.len {
+            let restart = self.seek(0);
+            self.playing = true;
+            return restart;
+        }
+        None
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.0).min(MAX_SPEED);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.0).max(MIN_SPEED);
+    }
+
+    /// How far through the recording, from 0 to 1.
+    #[must_use]
+    pub fn progress(&self) -> f32 {
+        if self.len == 0 {
+            1.0
+        } else {
+            self.step as f32 / self.len as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod playback_tests {
+    use super::*;
+
+    #[test]
+    fn plays_at_the_chosen_speed_and_stops_at_the_end() {
+        let mut playback = Playback::new(5);
+        assert_eq!(playback.tick(0.25), None);
+        assert_eq!(playback.tick(0.25), Some(1));
+
+        playback.faster();
+        assert_eq!(playback.tick(0.5), Some(3));
+        assert_eq!(playback.tick(10.0), Some(5));
+        assert!(!playback.playing);
+        assert_eq!(playback.tick(1.0), None);
+
+        assert_eq!(playback.toggle(), Some(0));
+        assert!(playback.playing);
+    }
+
+    #[test]
+    fn stepping_pauses_and_stays_in_range() {
+        let mut playback = Playback::new(3);
+        assert_eq!(playback.step_by(-1), None);
+        assert!(!playback.playing);
+        assert_eq!(playback.step_by(1), Some(1));
+        assert_eq!(playback.seek(10), Some(3));
+        assert_eq!(playback.progress(), 1.0);
+        assert_eq!(playback.step_by(-2), Some(1));
+    }
+
+    #[test]
+    fn speed_is_bounded() {
+        let mut playback = Playback::new(1);
+        for _ in 0..10 {
+            playback.faster();
+        }
+        assert_eq!(playback.speed, MAX_SPEED);
+        for _ in 0..10 {
+            playback.slower();
+        }
+        assert_eq!(playback.speed, MIN_SPEED);
+    }
+}
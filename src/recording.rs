@@ -0,0 +1,473 @@
+//! Games recorded move by move, to be replayed, shared and checked.
+//!
+//! A recording is the board size, spawn rules and seed of a game, the tiles
+//! it started with, then every move that changed the board along with the
+//! tiles spawned after it. The seed alone determines the spawns, so they are
+//! redundant: they make a recording readable without the generator and let
+//! [`Recording::verify`] catch a doctored one.
+//!
+//! Stored as RON, e.g. a game that has gone left then up:
+//!
+//! ```text
+//! (
+//!     width: 4,
+//!     height: 4,
+//!     rules: (values: [(2, 0.9), (4, 0.1)], per_move: 1, starting: 2),
+//!     seed: 2048,
+//!     start: [(x: 1, y: 0, value: 2), (x: 3, y: 2, value: 2)],
+//!     moves: [
+//!         (direction: Left, spawns: [(x: 2, y: 3, value: 4)]),
+//!         (direction: Up, spawns: [(x: 0, y: 0, value: 2)]),
+//!     ],
+//! )
+//! ```
+//!
+//! `x` counts cells from the left and `y` from the bottom. The move that won
+//! the game has `won: true` and no spawns, as the game paused on the win
+//! screen.
+//!
+//! A game carried on from a shared position has `from_position: true` and
+//! the score it started on: its `start` is every tile of that position, and
+//! the generator starts afresh from the seed after it.
+
+use std::fmt;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::grid::{value_to_rank, Direction, Grid, MoveOutcome, SpawnRules};
+use crate::rng::GameRng;
+use crate::storage::Document;
+
+/// Finished games kept in [`Replays`].
+pub const MAX_REPLAYS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Spawn {
+    pub x: u32,
+    pub y: u32,
+    pub value: u64,
+}
+
+impl Spawn {
+    /// The tile `grid` holds at `coord`.
+    #[must_use]
+    pub fn read(grid: &Grid, coord: UVec2) -> Self {
+        Self {
+            x: coord.x,
+            y: coord.y,
+            value: grid.value_at(coord),
+        }
+    }
+
+    #[must_use]
+    pub fn coord(self) -> UVec2 {
+        UVec2::new(self.x, self.y)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMove {
+    pub direction: Direction,
+    pub spawns: Vec<Spawn>,
+    /// The move reached the last milestone, so nothing spawned.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub won: bool,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub width: usize,
+    pub height: usize,
+    pub rules: SpawnRules,
+    pub seed: u64,
+    pub start: Vec<Spawn>,
+    pub moves: Vec<RecordedMove>,
+    /// Whether `start` is a whole position rather than the seed's starting
+    /// tiles.
+    #[serde(default)]
+    pub from_position: bool,
+    /// Points scored before the first move.
+    #[serde(default)]
+    pub score: u64,
+    /// Moves taken back, most recent last, until another move is played.
+    #[serde(skip)]
+    undone: Vec<RecordedMove>,
+}
+
+/// Why a recording does not hold up.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayError {
+    /// The starting tiles are not what the seed gives.
+    Start,
+    /// Move `n` (from 0) did not change the board.
+    Illegal(usize),
+    /// The tiles spawned after move `n` are not what the seed gives.
+    Spawns(usize),
+    /// A spawn lands outside the board, on a tile, or is not a tile value.
+    InvalidSpawn(Spawn),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Start => write!(f, "the starting tiles do not match the seed"),
+            Self::Illegal(n) => write!(f, "move {} does not change the board", n + 1),
+            Self::Spawns(n) => write!(f, "the spawns after move {} do not match the seed", n + 1),
+            Self::InvalidSpawn(spawn) => write!(
+                f,
+                "no {} can spawn at ({}, {})",
+                spawn.value, spawn.x, spawn.y
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Recording {
+    /// Start recording a game from its freshly started board.
+    #[must_use]
+    pub fn new(grid: &Grid, seed: u64, start: &[UVec2]) -> Self {
+        Self {
+            width: grid.width,
+            height: grid.height,
+            rules: grid.rules.clone(),
+            seed,
+            start: spawns(grid, start),
+            moves: Vec::new(),
+            from_position: false,
+            score: 0,
+            undone: Vec::new(),
+        }
+    }
+
+    /// Start recording a game carried on from the board as it is, with
+    /// `score` points already made.
+    #[must_use]
+    pub fn from_position(grid: &Grid, seed: u64, score: u64) -> Self {
+        let tiles: Vec<UVec2> = (0..grid.cells.len())
+            .filter(|&index| grid.cells[index] != 0)
+            .map(|index| Grid::index_to_coord(index, grid.width, grid.height))
+            .collect();
+        Self {
+            from_position: true,
+            score,
+            ..Self::new(grid, seed, &tiles)
+        }
+    }
+
+    /// Record a move and the tiles it spawned on `grid`.
+    pub fn push(&mut self, direction: Direction, grid: &Grid, spawned: &[UVec2]) {
+        self.undone.clear();
+        self.moves.push(RecordedMove {
+            direction,
+            spawns: spawns(grid, spawned),
+            won: false,
+        });
+    }
+
+    /// Record the move that won the game, after which nothing spawned.
+    pub fn push_win(&mut self, direction: Direction) {
+        self.undone.clear();
+        self.moves.push(RecordedMove {
+            direction,
+            spawns: Vec::new(),
+            won: true,
+        });
+    }
+
+    /// Forget the last move, keeping it for [`Self::redo`].
+    pub fn undo(&mut self) {
+        if let Some(last) = self.moves.pop() {
+            self.undone.push(last);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(next) = self.undone.pop() {
+            self.moves.push(next);
+        }
+    }
+
+    /// Empty board with the recorded size and rules.
+    #[must_use]
+    pub fn empty_grid(&self) -> Grid {
+        Grid::with_size(self.width, self.height).with_rules(self.rules.clone())
+    }
+
+    /// The board after the first `moves` moves, placing the recorded spawns
+    /// rather than drawing them, and the score so far.
+    pub fn position(&self, moves: usize) -> Result<(Grid, u64), ReplayError> {
+        let mut grid = self.empty_grid();
+        place(&mut grid, &self.start)?;
+        let mut score = self.score;
+        for n in 0..moves.min(self.moves.len()) {
+            score = score.saturating_add(self.play(&mut grid, n)?.score);
+        }
+        Ok((grid, score))
+    }
+
+    /// Play move `n` (from 0) on `grid`, the board before it, followed by
+    /// its recorded spawns.
+    pub fn play(&self, grid: &mut Grid, n: usize) -> Result<MoveOutcome, ReplayError> {
+        let recorded = &self.moves[n];
+        let outcome = grid.slide(recorded.direction);
+        if !outcome.changed {
+            return Err(ReplayError::Illegal(n));
+        }
+        place(grid, &recorded.spawns)?;
+        Ok(outcome)
+    }
+
+    /// Play the game again from the seed and check every move was legal
+    /// and every spawn is the one the generator gives. Returns the final
+    /// board.
+    pub fn verify(&self) -> Result<Grid, ReplayError> {
+        let mut rng = GameRng::from_seed(self.seed);
+        let mut grid = self.empty_grid();
+        if self.from_position {
+            place(&mut grid, &self.start)?;
+        } else {
+            let start = grid.start(&mut rng);
+            if spawns(&grid, &start) != self.start {
+                return Err(ReplayError::Start);
+            }
+        }
+
+        for (n, recorded) in self.moves.iter().enumerate() {
+            let before = grid.max_value();
+            if !grid.slide(recorded.direction).changed {
+                return Err(ReplayError::Illegal(n));
+            }
+            let spawned = if recorded.won {
+                // Only a new largest tile can win.
+                if grid.max_value() <= before {
+                    return Err(ReplayError::Spawns(n));
+                }
+                Vec::new()
+            } else {
+                grid.spawn_tiles(&mut rng)
+            };
+            if spawns(&grid, &spawned) != recorded.spawns {
+                return Err(ReplayError::Spawns(n));
+            }
+        }
+        Ok(grid)
+    }
+}
+
+fn spawns(grid: &Grid, coords: &[UVec2]) -> Vec<Spawn> {
+    coords
+        .iter()
+        .map(|&coord| Spawn::read(grid, coord))
+        .collect()
+}
+
+/// Put recorded tiles on empty cells of `grid`.
+fn place(grid: &mut Grid, spawns: &[Spawn]) -> Result<(), ReplayError> {
+    for &spawn in spawns {
+        let (x, y) = (spawn.x as usize, spawn.y as usize);
+        let rank = value_to_rank(spawn.value).filter(|&rank| rank != 0);
+        match rank {
+            Some(rank) if x < grid.width && y < grid.height => {
+                let index = Grid::index_2d(x, y, grid.width, grid.height);
+                if grid.cells[index] != 0 {
+                    return Err(ReplayError::InvalidSpawn(spawn));
+                }
+                grid.cells[index] = rank;
+            }
+            _ => return Err(ReplayError::InvalidSpawn(spawn)),
+        }
+    }
+    Ok(())
+}
+
+/// The last finished games, oldest first.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Replays {
+    pub version: u32,
+    pub games: Vec<Recording>,
+}
+
+impl Replays {
+    /// Keep `recording`, dropping the oldest game beyond [`MAX_REPLAYS`].
+    pub fn add(&mut self, recording: Recording) {
+        self.version = Self::VERSION;
+        self.games.push(recording);
+        let excess = self.games.len().saturating_sub(MAX_REPLAYS);
+        self.games.drain(..excess);
+    }
+}
+
+impl Document for Replays {
+    const FILE: &'static str = "replays.ron";
+    const VERSION: u32 = 1;
+}
+
+#[cfg(test)]
+mod recording_tests {
+    use super::*;
+    use crate::share;
+
+    /// Play `directions` (skipping those that do nothing) from `seed`.
+    fn play(seed: u64, directions: &[Direction]) -> (Grid, u64, Recording) {
+        play_to(None, seed, directions)
+    }
+
+    /// Like [`play`], pausing without a spawn on building `target`.
+    fn play_to(target: Option<u64>, seed: u64, directions: &[Direction]) -> (Grid, u64, Recording) {
+        let mut rng = GameRng::from_seed(seed);
+        let mut grid = Grid::new();
+        let start = grid.start(&mut rng);
+        let mut recording = Recording::new(&grid, seed, &start);
+        let mut score = 0;
+        for &direction in directions {
+            let before = grid.max_value();
+            let outcome = grid.slide(direction);
+            if outcome.changed {
+                score += outcome.score;
+                if target.is_some_and(|target| before < target && grid.max_value() >= target) {
+                    recording.push_win(direction);
+                } else {
+                    let spawned = grid.spawn_tiles(&mut rng);
+                    recording.push(direction, &grid, &spawned);
+                }
+            }
+        }
+        (grid, score, recording)
+    }
+
+    const DIRECTIONS: [Direction; 12] = [
+        Direction::Left,
+        Direction::Down,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+        Direction::Up,
+        Direction::Left,
+        Direction::Down,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+        Direction::Down,
+    ];
+
+    #[test]
+    fn recorded_games_verify_and_replay() {
+        let (grid, score, recording) = play(5, &DIRECTIONS);
+
+        assert_eq!(recording.verify().unwrap().values(), grid.values());
+        let (replayed, replayed_score) = recording.position(recording.moves.len()).unwrap();
+        assert_eq!(replayed.values(), grid.values());
+        assert_eq!(replayed_score, score);
+
+        let (first, _) = recording.position(0).unwrap();
+        assert_eq!(
+            first.cells.iter().filter(|&&rank| rank != 0).count(),
+            recording.start.len()
+        );
+    }
+
+    #[test]
+    fn tampered_recordings_fail() {
+        let (_, _, recording) = play(5, &DIRECTIONS);
+
+        let mut lucky = recording.clone();
+        lucky.moves[3].spawns[0].value = 4096;
+        assert_eq!(lucky.verify().unwrap_err(), ReplayError::Spawns(3));
+
+        let mut spared = recording.clone();
+        spared.moves[4].spawns.clear();
+        assert_eq!(spared.verify().unwrap_err(), ReplayError::Spawns(4));
+
+        let mut other_seed = recording.clone();
+        other_seed.seed = 6;
+        assert!(other_seed.verify().is_err());
+
+        let mut stacked = recording;
+        stacked.start[1] = stacked.start[0];
+        assert_eq!(
+            stacked.position(0).unwrap_err(),
+            ReplayError::InvalidSpawn(stacked.start[0])
+        );
+    }
+
+    #[test]
+    fn the_winning_move_spawns_nothing() {
+        let (grid, _, recording) = play_to(Some(8), 5, &DIRECTIONS);
+        let won = recording
+            .moves
+            .iter()
+            .position(|recorded| recorded.won)
+            .expect("8 not reached");
+        assert!(recording.moves[won].spawns.is_empty());
+        assert!(won + 1 < recording.moves.len());
+        assert_eq!(recording.verify().unwrap().values(), grid.values());
+
+        let mut unflagged = recording.clone();
+        unflagged.moves[won].won = false;
+        assert_eq!(unflagged.verify().unwrap_err(), ReplayError::Spawns(won));
+
+        let mut flagged = recording;
+        flagged.moves[won + 1].won = true;
+        assert_eq!(flagged.verify().unwrap_err(), ReplayError::Spawns(won + 1));
+    }
+
+    #[test]
+    fn games_carried_on_from_a_shared_code_verify() {
+        let (grid, score, _) = play(3, &DIRECTIONS[..6]);
+        let shared = share::decode(&share::encode(&grid, score, 11)).unwrap();
+        let mut grid = shared.grid;
+        let mut rng = GameRng::from_seed(shared.seed);
+        let mut recording = Recording::from_position(&grid, shared.seed, shared.score);
+
+        let direction = Direction::ALL
+            .into_iter()
+            .find(|&direction| grid.clone().slide(direction).changed)
+            .unwrap();
+        let points = grid.slide(direction).score;
+        let spawned = grid.spawn_tiles(&mut rng);
+        recording.push(direction, &grid, &spawned);
+
+        assert_eq!(recording.verify().unwrap().values(), grid.values());
+        let (replayed, replayed_score) = recording.position(1).unwrap();
+        assert_eq!(replayed.values(), grid.values());
+        assert_eq!(replayed_score, score + points);
+        assert_eq!(recording.position(0).unwrap().1, score);
+
+        let forged = Recording {
+            score: u64::MAX,
+            ..recording
+        };
+        assert_eq!(forged.position(1).unwrap().1, u64::MAX);
+    }
+
+    #[test]
+    fn undo_and_redo_follow_the_game() {
+        let (_, _, mut recording) = play(5, &DIRECTIONS);
+        let full = recording.clone();
+
+        recording.undo();
+        recording.undo();
+        assert_eq!(recording.moves.len(), full.moves.len() - 2);
+        recording.redo();
+        recording.redo();
+        assert_eq!(recording, full);
+    }
+
+    #[test]
+    fn replays_keep_the_latest_games() {
+        let (_, _, recording) = play(1, &DIRECTIONS[..2]);
+        let mut replays = Replays::default();
+        for seed in 0..MAX_REPLAYS as u64 + 3 {
+            replays.add(Recording {
+                seed,
+                ..recording.clone()
+            });
+        }
+        assert_eq!(replays.games.len(), MAX_REPLAYS);
+        assert_eq!(replays.games[0].seed, 3);
+        assert_eq!(replays.version, Replays::VERSION);
+    }
+}
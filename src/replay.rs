@@ -0,0 +1,401 @@
+//! Watching a recorded game on the board.
+//!
+//! R opens the game in progress, Shift+R the last finished one. Space plays
+//! or pauses, the arrows step (left/right) or change the speed (up/down),
+//! Home and End jump, and the bar under the controls can be dragged along.
+//! R or Backspace goes back to the game.
+
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+use shadowmitia_2048::grid::Grid;
+use shadowmitia_2048::playback::Playback;
+use shadowmitia_2048::recording::{Recording, Replays};
+use shadowmitia_2048::storage::Storage;
+
+use crate::tween::Cell;
+use crate::{
+    animate_tiles, cleanup_system, fits_window, AppState, GameFont, Score, TilesMoved,
+    HEADER_HEIGHT, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON,
+};
+
+/// The recording on screen, and the game it interrupted.
+#[derive(Resource)]
+struct ReplayViewer {
+    recording: Recording,
+    playback: Playback,
+    /// Step the board shows, `None` until it is first built.
+    shown: Option<usize>,
+    /// The board and score of the game in progress, put back on close.
+    live: (Grid, u64),
+}
+
+#[derive(Component, Clone, Copy)]
+pub enum ReplayButton {
+    Start,
+    Back,
+    PlayPause,
+    Forward,
+    End,
+    Slower,
+    Faster,
+    Close,
+}
+
+#[derive(Component)]
+struct ReplayUI;
+
+#[derive(Component)]
+struct ReplayLabel;
+
+#[derive(Component)]
+struct Timeline;
+
+#[derive(Component)]
+struct TimelineFill;
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Update, open_replay.run_if(in_state(AppState::InGame)))
+        .add_systems(OnEnter(AppState::Replay), replay_ui)
+        .add_systems(
+            OnExit(AppState::Replay),
+            (cleanup_system::<ReplayUI>, close_replay),
+        )
+        .add_systems(
+            Update,
+            (
+                (replay_keys, replay_buttons, scrub_timeline),
+                advance_replay,
+                update_replay_ui,
+            )
+                .chain()
+                .before(animate_tiles)
+                .run_if(in_state(AppState::Replay)),
+        );
+}
+
+fn open_replay(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    recording: Res<Recording>,
+    grid: Res<Grid>,
+    score: Res<Score>,
+    mut storage: ResMut<Storage>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if !input.just_pressed(KeyCode::KeyR) {
+        return;
+    }
+
+    let recording = if input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        match storage.load::<Replays>() {
+            Ok(replays) => replays.and_then(|mut replays| replays.games.pop()),
+            Err(err) => {
+                warn!("could not load the replays: {err}");
+                None
+            }
+        }
+    } else {
+        Some(recording.clone())
+    };
+    let Some(recording) = recording else {
+        info!("no finished game to replay");
+        return;
+    };
+    if !fits_window(&grid, recording.width, recording.height) {
+        info!(
+            "cannot replay a {}x{} game on this board",
+            recording.width, recording.height
+        );
+        return;
+    }
+
+    commands.insert_resource(ReplayViewer {
+        playback: Playback::new(recording.moves.len()),
+        recording,
+        shown: None,
+        live: (grid.clone(), score.current),
+    });
+    next_state.set(AppState::Replay);
+}
+
+fn close_replay(
+    mut commands: Commands,
+    viewer: Res<ReplayViewer>,
+    mut grid: ResMut<Grid>,
+    mut score: ResMut<Score>,
+    tiles: Query<Entity, With<Cell>>,
+) {
+    (*grid, score.current) = viewer.live.clone();
+    for entity in &tiles {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<ReplayViewer>();
+}
+
+fn replay_keys(
+    input: Res<ButtonInput<KeyCode>>,
+    mut viewer: ResMut<ReplayViewer>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let playback = &mut viewer.playback;
+    if input.just_pressed(KeyCode::Space) {
+        playback.toggle();
+    }
+    if input.just_pressed(KeyCode::ArrowRight) {
+        playback.step_by(1);
+    }
+    if input.just_pressed(KeyCode::ArrowLeft) {
+        playback.step_by(-1);
+    }
+    if input.just_pressed(KeyCode::ArrowUp) {
+        playback.faster();
+    }
+    if input.just_pressed(KeyCode::ArrowDown) {
+        playback.slower();
+    }
+    if input.just_pressed(KeyCode::Home) {
+        playback.seek(0);
+    }
+    if input.just_pressed(KeyCode::End) {
+        playback.seek(playback.len);
+    }
+    if input.any_just_pressed([KeyCode::KeyR, KeyCode::Backspace]) {
+        next_state.set(AppState::InGame);
+    }
+}
+
+fn replay_buttons(
+    mut viewer: ResMut<ReplayViewer>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut interaction_query: Query<
+        (&Interaction, &ReplayButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+) {
+    for (interaction, button, mut color) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                let playback = &mut viewer.playback;
+                match button {
+                    ReplayButton::Start => {
+                        playback.seek(0);
+                    }
+                    ReplayButton::Back => {
+                        playback.step_by(-1);
+                    }
+                    ReplayButton::PlayPause => {
+                        playback.toggle();
+                    }
+                    ReplayButton::Forward => {
+                        playback.step_by(1);
+                    }
+                    ReplayButton::End => {
+                        playback.seek(playback.len);
+                    }
+                    ReplayButton::Slower => playback.slower(),
+                    ReplayButton::Faster => playback.faster(),
+                    ReplayButton::Close => next_state.set(AppState::InGame),
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+/// Holding the mouse on the timeline jumps to the move under the cursor.
+fn scrub_timeline(
+    mut viewer: ResMut<ReplayViewer>,
+    timeline: Query<(&Interaction, &RelativeCursorPosition), With<Timeline>>,
+) {
+    for (interaction, cursor) in &timeline {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let Some(position) = cursor.normalized {
+            let playback = &mut viewer.playback;
+            let step = (position.x.clamp(0.0, 1.0) * playback.len as f32).round() as usize;
+            playback.playing = false;
+            playback.seek(step);
+        }
+    }
+}
+
+/// Bring the board to the playback's step. A single step forward animates
+/// like a move in the game; anything else rebuilds the tiles.
+#[allow(clippy::too_many_arguments)]
+fn advance_replay(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut viewer: ResMut<ReplayViewer>,
+    mut grid: ResMut<Grid>,
+    mut score: ResMut<Score>,
+    mut moved_events: EventWriter<TilesMoved>,
+    mut next_state: ResMut<NextState<AppState>>,
+    tiles: Query<Entity, With<Cell>>,
+) {
+    let viewer = &mut *viewer;
+    viewer.playback.tick(time.delta_seconds());
+    let step = viewer.playback.step;
+    if viewer.shown == Some(step) {
+        return;
+    }
+
+    let result = match viewer.shown {
+        Some(shown) if step == shown + 1 => {
+            viewer.recording.play(&mut grid, shown).map(|outcome| {
+                score.current = score.current.saturating_add(outcome.score);
+                moved_events.send(TilesMoved(outcome));
+            })
+        }
+        _ => viewer.recording.position(step).map(|(position, points)| {
+            *grid = position;
+            score.current = points;
+            for entity in &tiles {
+                commands.entity(entity).despawn_recursive();
+            }
+        }),
+    };
+
+    match result {
+        Ok(()) => viewer.shown = Some(step),
+        Err(err) => {
+            warn!("cannot replay this game: {err}");
+            next_state.set(AppState::InGame);
+        }
+    }
+}
+
+fn update_replay_ui(
+    viewer: Res<ReplayViewer>,
+    mut label: Query<&mut Text, With<ReplayLabel>>,
+    mut fill: Query<&mut Style, With<TimelineFill>>,
+) {
+    let playback = &viewer.playback;
+    for mut text in &mut label {
+        text.sections[0].value = format!(
+            "{}/{} {}x {}",
+            playback.step,
+            playback.len,
+            playback.speed,
+            if playback.playing {
+                "playing"
+            } else {
+                "paused"
+            }
+        );
+    }
+    for mut style in &mut fill {
+        style.width = Val::Percent(playback.progress() * 100.0);
+    }
+}
+
+/// The controls, laid over the score header.
+fn replay_ui(mut commands: Commands, font: Res<GameFont>) {
+    let text_style = |color| TextStyle {
+        font: font.0.clone(),
+        font_size: 24.0,
+        color,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                z_index: ZIndex::Global(2),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::SpaceEvenly,
+                    width: Val::Percent(100.0),
+                    height: Val::Px(HEADER_HEIGHT),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::WHITE),
+                ..default()
+            },
+            ReplayUI,
+        ))
+        .with_children(|builder| {
+            builder
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(6.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|builder| {
+                    for (label, button) in [
+                        ("|<", ReplayButton::Start),
+                        ("<", ReplayButton::Back),
+                        ("Play", ReplayButton::PlayPause),
+                        (">", ReplayButton::Forward),
+                        (">|", ReplayButton::End),
+                        ("-", ReplayButton::Slower),
+                        ("+", ReplayButton::Faster),
+                        ("Back to game", ReplayButton::Close),
+                    ] {
+                        builder
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                                        ..default()
+                                    },
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                button,
+                            ))
+                            .with_children(|builder| {
+                                builder.spawn(TextBundle::from_section(
+                                    label,
+                                    text_style(Color::WHITE),
+                                ));
+                            });
+                    }
+                    builder.spawn((
+                        TextBundle::from_section("", text_style(Color::BLACK)),
+                        ReplayLabel,
+                    ));
+                });
+
+            builder
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(90.0),
+                            height: Val::Px(12.0),
+                            ..default()
+                        },
+                        background_color: BackgroundColor(Color::hex("cdc1b4").unwrap()),
+                        ..default()
+                    },
+                    Interaction::default(),
+                    RelativeCursorPosition::default(),
+                    Timeline,
+                ))
+                .with_children(|builder| {
+                    builder.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(0.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            background_color: BackgroundColor(Color::hex("edc22e").unwrap()),
+                            ..default()
+                        },
+                        TimelineFill,
+                    ));
+                });
+        });
+}
@@ -0,0 +1,86 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// Source of randomness for tile spawns.
+///
+/// ChaCha8's output is fully specified by its seed, independently of the
+/// platform or pointer width, so a seed and a list of moves always replay
+/// the same game.
+///
+/// It serialises as its seed and how far into the stream it has read.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "RngState", into = "RngState")]
+pub struct GameRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl GameRng {
+    #[must_use]
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Generator with a fresh seed taken from the OS.
+    #[must_use]
+    pub fn from_entropy() -> Self {
+        Self::from_seed(thread_rng().gen())
+    }
+
+    /// Seeded generator if `seed` is given, otherwise a fresh one.
+    #[must_use]
+    pub fn new(seed: Option<u64>) -> Self {
+        seed.map_or_else(Self::from_entropy, Self::from_seed)
+    }
+
+    #[must_use]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RngState {
+    seed: u64,
+    word_pos: u128,
+}
+
+impl From<GameRng> for RngState {
+    fn from(rng: GameRng) -> Self {
+        Self {
+            seed: rng.seed,
+            word_pos: rng.rng.get_word_pos(),
+        }
+    }
+}
+
+impl From<RngState> for GameRng {
+    fn from(state: RngState) -> Self {
+        let mut rng = Self::from_seed(state.seed);
+        rng.rng.set_word_pos(state.word_pos);
+        rng
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
@@ -0,0 +1,192 @@
+//! Judging moves by playing them out at random.
+//!
+//! Every legal move is played, then the game carries on with random moves
+//! until it is lost; the move whose games scored the most on average wins.
+//! The `n`th game after each move uses the same seed, so the moves are
+//! compared over the same luck. Games are spread over threads and the
+//! result does not depend on how many.
+
+use std::thread;
+
+use rand::seq::SliceRandom;
+
+use crate::grid::{Direction, Grid};
+use crate::rng::GameRng;
+
+/// How many games to play out, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rollouts {
+    /// Games played after each legal move.
+    pub games: u32,
+    /// Random moves per game before it is cut short, `None` to play on
+    /// until it is lost.
+    pub max_moves: Option<u32>,
+    /// Threads to share the games between; 1 plays them on the calling
+    /// thread.
+    pub threads: usize,
+    /// Seed of the first game after each move.
+    pub seed: u64,
+}
+
+impl Default for Rollouts {
+    fn default() -> Self {
+        Self {
+            games: 100,
+            max_moves: None,
+            threads: thread::available_parallelism().map_or(1, usize::from),
+            seed: 0,
+        }
+    }
+}
+
+/// What the games played out showed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /// The move to play, `None` if the game is over.
+    pub best: Option<Direction>,
+    /// Average points scored after every legal move, the move's own
+    /// included, best first.
+    pub values: Vec<(Direction, f64)>,
+    /// Games played in all.
+    pub games: u64,
+}
+
+impl Rollouts {
+    /// The move to play on `grid`, `None` if there is none.
+    #[must_use]
+    pub fn best_move(&self, grid: &Grid) -> Option<Direction> {
+        self.analyse(grid).best
+    }
+
+    #[must_use]
+    pub fn analyse(&self, grid: &Grid) -> Analysis {
+        let legal: Vec<Direction> = Direction::ALL
+            .into_iter()
+            .filter(|&direction| grid.clone().slide(direction).changed)
+            .collect();
+        let jobs: Vec<(usize, u64)> = (0..legal.len())
+            .flat_map(|k| (0..u64::from(self.games)).map(move |n| (k, n)))
+            .collect();
+
+        let play = |jobs: &[(usize, u64)]| {
+            jobs.iter()
+                .map(|&(k, n)| (k, self.play_out(grid, legal[k], n)))
+                .collect::<Vec<_>>()
+        };
+        let threads = self.threads.clamp(1, jobs.len().max(1));
+        let scores = if threads == 1 {
+            play(&jobs)
+        } else {
+            thread::scope(|scope| {
+                let handles: Vec<_> = jobs
+                    .chunks(jobs.len().div_ceil(threads))
+                    .map(|chunk| scope.spawn(move || play(chunk)))
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().expect("rollout thread panicked"))
+                    .collect()
+            })
+        };
+
+        let mut totals = vec![0u64; legal.len()];
+        for (k, score) in scores {
+            totals[k] += score;
+        }
+        let mut values: Vec<(Direction, f64)> = legal
+            .iter()
+            .zip(totals)
+            .map(|(&direction, total)| (direction, total as f64 / f64::from(self.games.max(1))))
+            .collect();
+        values.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        Analysis {
+            best: values.first().map(|&(direction, _)| direction),
+            values,
+            games: jobs.len() as u64,
+        }
+    }
+
+    /// Points scored by game `n` after playing `direction` on `grid`.
+    fn play_out(&self, grid: &Grid, direction: Direction, n: u64) -> u64 {
+        let mut rng = GameRng::from_seed(self.seed.wrapping_add(n));
+        let mut grid = grid.clone();
+        let mut score = grid.slide(direction).score;
+        grid.spawn_tiles(&mut rng);
+
+        for _ in 0..self.max_moves.unwrap_or(u32::MAX) {
+            let mut directions = Direction::ALL;
+            directions.shuffle(&mut rng);
+            // A slide that changes nothing leaves the board as it was.
+            let Some(outcome) = directions
+                .into_iter()
+                .map(|direction| grid.slide(direction))
+                .find(|outcome| outcome.changed)
+            else {
+                break;
+            };
+            score += outcome.score;
+            grid.spawn_tiles(&mut rng);
+        }
+        score
+    }
+}
+
+#[cfg(test)]
+mod rollout_tests {
+    use super::*;
+
+    #[test]
+    fn threads_do_not_change_the_result() {
+        let mut rng = GameRng::from_seed(4);
+        let mut grid = Grid::new();
+        grid.start(&mut rng);
+        let rollouts = Rollouts {
+            games: 20,
+            threads: 1,
+            ..Rollouts::default()
+        };
+
+        let alone = rollouts.analyse(&grid);
+        let shared = Rollouts {
+            threads: 3,
+            ..rollouts
+        }
+        .analyse(&grid);
+        assert_eq!(alone, shared);
+        assert_eq!(alone.games, 20 * alone.values.len() as u64);
+    }
+
+    #[test]
+    fn short_games_score_the_move_itself() {
+        #[rustfmt::skip]
+        let grid = Grid::from_values(2, 2, &[
+            8, 8,
+            2, 4,
+        ]);
+        let analysis = Rollouts {
+            games: 5,
+            max_moves: Some(0),
+            ..Rollouts::default()
+        }
+        .analyse(&grid);
+        assert_eq!(analysis.values[0].1, 16.0);
+        assert!(matches!(
+            analysis.best,
+            Some(Direction::Left | Direction::Right)
+        ));
+        assert_eq!(analysis.values.len(), 2);
+    }
+
+    #[test]
+    fn nothing_to_play_on_a_lost_board() {
+        #[rustfmt::skip]
+        let lost = Grid::from_values(2, 2, &[
+            2, 4,
+            4, 2,
+        ]);
+        let analysis = Rollouts::default().analyse(&lost);
+        assert_eq!(analysis.best, None);
+        assert_eq!(analysis.games, 0);
+    }
+}
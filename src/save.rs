@@ -0,0 +1,161 @@
+//! The game in progress, kept on disk so it survives closing the window.
+
+use serde::{Deserialize, Serialize};
+
+use crate::grid::{value_to_rank, Grid};
+use crate::recording::Recording;
+use crate::rng::GameRng;
+use crate::storage::Document;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub version: u32,
+    pub grid: Grid,
+    pub score: u64,
+    /// Largest milestone already celebrated.
+    pub has_won: u64,
+    pub rng: GameRng,
+    pub moves: u64,
+    /// Hints asked for; games saved before hints existed had none.
+    #[serde(default)]
+    pub hints: u64,
+    pub recording: Recording,
+    /// Carried on from a shared position, so the score is not all the
+    /// player's own.
+    #[serde(default)]
+    pub imported: bool,
+}
+
+impl SavedGame {
+    #[must_use]
+    pub fn new(
+        grid: Grid,
+        score: u64,
+        has_won: u64,
+        rng: GameRng,
+        moves: u64,
+        recording: Recording,
+    ) -> Self {
+        Self {
+            version: Self::VERSION,
+            grid,
+            score,
+            has_won,
+            rng,
+            moves,
+            hints: 0,
+            recording,
+            imported: false,
+        }
+    }
+}
+
+impl Document for SavedGame {
+    const FILE: &'static str = "game.ron";
+    // Version 1, from before games were recorded, only ever existed in
+    // development builds and is not carried over.
+    const VERSION: u32 = 2;
+
+    fn validate(&self) -> Result<(), String> {
+        let grid = &self.grid;
+        if grid.width < 2 || grid.height < 2 || grid.cells.len() != grid.width * grid.height {
+            return Err("board dimensions do not match its cells".to_string());
+        }
+        if (self.recording.width, self.recording.height) != (grid.width, grid.height) {
+            return Err("the recording is of another board".to_string());
+        }
+        let values = &grid.rules.values;
+        if values.is_empty() {
+            return Err("spawn rules without values".to_string());
+        }
+        if let Some(&(value, _)) = values
+            .iter()
+            .find(|&&(value, _)| !matches!(value_to_rank(value), Some(rank) if rank > 0))
+        {
+            return Err(format!("{value} is not a tile to spawn"));
+        }
+        if values
+            .iter()
+            .any(|&(_, weight)| !weight.is_finite() || weight < 0.0)
+        {
+            return Err("spawn weights must be finite and not negative".to_string());
+        }
+        if values.iter().map(|&(_, weight)| weight).sum::<f32>() <= 0.0 {
+            return Err("spawn weights add up to nothing".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod save_tests {
+    use rand::prelude::*;
+
+    use super::*;
+    use crate::grid::{Direction, SpawnRules};
+    use crate::storage::{MemoryBackend, Storage, StorageError};
+
+    #[test]
+    fn round_trips_and_resumes_the_same_spawns() {
+        let mut rng = GameRng::from_seed(77);
+        let mut grid = Grid::with_size(5, 4).with_rules(SpawnRules::hard());
+        let start = grid.start(&mut rng);
+        let mut recording = Recording::new(&grid, 77, &start);
+        let _ = grid.slide(Direction::Left);
+        let spawned = grid.spawn_tiles(&mut rng);
+        recording.push(Direction::Left, &grid, &spawned);
+
+        let mut storage = Storage::new(MemoryBackend::default());
+        storage
+            .store(&SavedGame::new(
+                grid.clone(),
+                12,
+                2048,
+                rng.clone(),
+                1,
+                recording.clone(),
+            ))
+            .unwrap();
+        let loaded = storage.load::<SavedGame>().unwrap().unwrap();
+
+        assert_eq!(loaded.grid.width, 5);
+        assert_eq!(loaded.grid.values(), grid.values());
+        assert_eq!(loaded.grid.rules, SpawnRules::hard());
+        assert_eq!((loaded.score, loaded.has_won, loaded.moves), (12, 2048, 1));
+        assert_eq!(loaded.rng.seed(), 77);
+        assert_eq!(loaded.recording, recording);
+
+        let mut resumed = loaded.rng;
+        assert_eq!(resumed.next_u64(), rng.next_u64());
+    }
+
+    #[test]
+    fn rejects_boards_that_do_not_add_up() {
+        let mut storage = Storage::new(MemoryBackend::default());
+        let grid = Grid::new();
+        let recording = Recording::new(&grid, 1, &[]);
+        let saved = SavedGame::new(grid, 0, 0, GameRng::from_seed(1), 0, recording);
+        assert_eq!(saved.validate(), Ok(()));
+
+        let mut narrowed = saved.clone();
+        narrowed.grid.width = 3;
+        storage.store(&narrowed).unwrap();
+        assert!(matches!(
+            storage.load::<SavedGame>(),
+            Err(StorageError::Corrupt { .. })
+        ));
+
+        for values in [
+            vec![(3, 1.0)],
+            vec![(0, 1.0)],
+            vec![(2, 1.0), (4, -0.5)],
+            vec![(2, f32::NAN)],
+            vec![(2, f32::INFINITY)],
+            vec![(2, 0.0), (4, 0.0)],
+        ] {
+            let mut odd_rules = saved.clone();
+            odd_rules.grid.rules.values = values.clone();
+            assert!(odd_rules.validate().is_err(), "{values:?}");
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Share codes: a position short enough to paste into a chat.
+//!
+//! A code is the board packed into bytes, written in URL-safe base64
+//! without padding. The bytes are:
+//!
+//! - the format: 1 when every tile fits in four bits (up to 32768), 2 when
+//!   each cell takes a whole byte;
+//! - the width and height;
+//! - the exponent of every cell, row by row from the bottom left, two cells
+//!   a byte (low half first) in format 1;
+//! - the score, seven bits a byte from the lowest, the top bit set on every
+//!   byte but the last;
+//! - the seed, eight bytes little endian;
+//! - a checksum, the sum of all the bytes before it.
+//!
+//! A 4x4 board makes a code of about 30 characters. The spawn rules are not
+//! part of it: a shared position plays by the rules of whoever loads it.
+
+use std::fmt;
+
+use crate::grid::Grid;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+const PACKED: u8 = 1;
+const WIDE: u8 = 2;
+
+/// Largest exponent that still has a `u64` value.
+const MAX_RANK: u8 = 63;
+
+/// A position read back from a share code.
+#[derive(Debug, Clone)]
+pub struct SharedPosition {
+    pub grid: Grid,
+    pub score: u64,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareError {
+    /// Not a character a code is written with.
+    Character(char),
+    /// The code stops short or runs on.
+    Length,
+    /// The checksum does not match, most likely a typo.
+    Checksum,
+    /// A format this version does not know.
+    Format(u8),
+    /// The bytes do not make a board.
+    Invalid,
+}
+
+impl fmt::Display for ShareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Character(c) => write!(f, "'{c}' cannot appear in a share code"),
+            Self::Length => write!(f, "the code is cut short or too long"),
+            Self::Checksum => write!(f, "the code has a typo"),
+            Self::Format(format) => write!(f, "unknown share code format {format}"),
+            Self::Invalid => write!(f, "the code does not hold a board"),
+        }
+    }
+}
+
+impl std::error::Error for ShareError {}
+
+/// The share code of `grid` with its score and seed.
+///
+/// Panics if the board is more than 255 cells across or tall.
+#[must_use]
+pub fn encode(grid: &Grid, score: u64, seed: u64) -> String {
+    let size = |extent: usize| u8::try_from(extent).expect("board too large to share");
+    let wide = grid.cells.iter().any(|&rank| rank > 0xf);
+
+    let mut bytes = vec![
+        if wide { WIDE } else { PACKED },
+        size(grid.width),
+        size(grid.height),
+    ];
+    if wide {
+        bytes.extend(&grid.cells);
+    } else {
+        bytes.extend(
+            grid.cells
+                .chunks(2)
+                .map(|pair| pair[0] | pair.get(1).map_or(0, |&rank| rank << 4)),
+        );
+    }
+
+    let mut rest = score;
+    while rest >= 0x80 {
+        bytes.push(rest as u8 | 0x80);
+        rest >>= 7;
+    }
+    bytes.push(rest as u8);
+
+    bytes.extend(seed.to_le_bytes());
+    bytes.push(checksum(&bytes));
+    to_base64(&bytes)
+}
+
+/// Read a share code. Surrounding whitespace is ignored. The board comes
+/// back with the default spawn rules.
+pub fn decode(code: &str) -> Result<SharedPosition, ShareError> {
+    let mut bytes = from_base64(code.trim())?;
+    let sum = bytes.pop().ok_or(ShareError::Length)?;
+    if checksum(&bytes) != sum {
+        return Err(ShareError::Checksum);
+    }
+
+    let mut rest = &bytes[..];
+    let header = take(&mut rest, 3)?;
+    let (format, width, height) = (header[0], header[1] as usize, header[2] as usize);
+    if width < 2 || height < 2 {
+        return Err(ShareError::Invalid);
+    }
+    let count = width * height;
+    let cells: Vec<u8> = match format {
+        PACKED => take(&mut rest, count.div_ceil(2))?
+            .iter()
+            .flat_map(|&byte| [byte & 0xf, byte >> 4])
+            .take(count)
+            .collect(),
+        WIDE => take(&mut rest, count)?.to_vec(),
+        other => return Err(ShareError::Format(other)),
+    };
+    if cells.iter().any(|&rank| rank > MAX_RANK) {
+        return Err(ShareError::Invalid);
+    }
+
+    let mut score = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = take(&mut rest, 1)?[0];
+        score |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if shift + 7 >= 64 {
+            return Err(ShareError::Invalid);
+        }
+    }
+
+    let seed = u64::from_le_bytes(take(&mut rest, 8)?.try_into().unwrap());
+    if !rest.is_empty() {
+        return Err(ShareError::Length);
+    }
+
+    let mut grid = Grid::with_size(width, height);
+    grid.cells = cells;
+    Ok(SharedPosition { grid, score, seed })
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// The first `n` bytes, moving `bytes` past them.
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], ShareError> {
+    if bytes.len() < n {
+        return Err(ShareError::Length);
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Ok(head)
+}
+
+fn to_base64(bytes: &[u8]) -> String {
+    let mut code = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (n, &byte)| {
+            bits | u32::from(byte) << (16 - 8 * n)
+        });
+        for n in 0..=chunk.len() {
+            code.push(ALPHABET[(bits >> (18 - 6 * n) & 0x3f) as usize] as char);
+        }
+    }
+    code
+}
+
+fn from_base64(code: &str) -> Result<Vec<u8>, ShareError> {
+    let mut bytes = Vec::with_capacity(code.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in code.chars() {
+        let digit = ALPHABET
+            .iter()
+            .position(|&letter| letter as char == c)
+            .ok_or(ShareError::Character(c))?;
+        bits = bits << 6 | digit as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod share_tests {
+    use super::*;
+
+    #[test]
+    fn positions_survive_a_round_trip() {
+        #[rustfmt::skip]
+        let grid = Grid::from_values(4, 4, &[
+            2048, 4, 2, 0,
+            512, 8, 0, 0,
+            16, 0, 0, 2,
+            0, 0, 0, 4,
+        ]);
+        let code = encode(&grid, 20_480, u64::MAX - 7);
+        assert!(code.len() <= 32, "{code} is too long");
+
+        let shared = decode(&format!(" {code}\n")).unwrap();
+        assert_eq!(shared.grid.values(), grid.values());
+        assert_eq!((shared.score, shared.seed), (20_480, u64::MAX - 7));
+    }
+
+    #[test]
+    fn large_tiles_and_odd_boards() {
+        let mut grid = Grid::with_size(3, 5);
+        grid.cells[0] = 17;
+        grid.cells[14] = 1;
+        let shared = decode(&encode(&grid, 0, 1)).unwrap();
+        assert_eq!((shared.grid.width, shared.grid.height), (3, 5));
+        assert_eq!(shared.grid.cells, grid.cells);
+    }
+
+    #[test]
+    fn damaged_codes_are_refused() {
+        let code = encode(&Grid::from_values(2, 2, &[2, 0, 0, 4]), 4, 9);
+
+        let mut typo = code.clone().into_bytes();
+        typo[4] = if typo[4] == b'A' { b'B' } else { b'A' };
+        let typo = String::from_utf8(typo).unwrap();
+        assert_eq!(decode(&typo).unwrap_err(), ShareError::Checksum);
+
+        assert_eq!(
+            decode(&format!("{code}!")).unwrap_err(),
+            ShareError::Character('!')
+        );
+        assert!(decode(&code[..code.len() - 4]).is_err());
+        assert_eq!(decode("").unwrap_err(), ShareError::Length);
+    }
+}
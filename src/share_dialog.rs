@@ -0,0 +1,394 @@
+//! Sharing the position on the board, and loading someone else's.
+//!
+//! P or the Share button opens a dialog with the code of the current
+//! position. Ctrl+C copies it; a code can be typed or pasted with Ctrl+V,
+//! then Enter loads it in place of the game in progress.
+
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+use shadowmitia_2048::grid::{Grid, SpawnRules};
+use shadowmitia_2048::history::History;
+use shadowmitia_2048::recording::Recording;
+use shadowmitia_2048::rng::GameRng;
+use shadowmitia_2048::save::SavedGame;
+use shadowmitia_2048::share::{self, SharedPosition};
+
+use crate::options::Options;
+use crate::tween::Cell;
+use crate::{
+    cleanup_system, fits_window, AppState, GameFont, GameStats, HasWon, Score, WinCondition,
+    HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON,
+};
+
+/// Longest code the dialog takes, well past the largest board.
+const MAX_CODE_LEN: usize = 200;
+
+#[derive(Resource, Default)]
+struct ShareDialog {
+    /// Code of the position on the board.
+    code: String,
+    /// What has been typed or pasted so far.
+    input: String,
+    /// Why the last code did not load, or what was copied.
+    message: String,
+}
+
+#[derive(Component, Clone, Copy)]
+pub enum ShareButton {
+    Copy,
+    Paste,
+    Load,
+    Close,
+}
+
+/// A code read from the dialog, to be played.
+#[derive(Event, Clone)]
+struct LoadPosition(SharedPosition);
+
+#[derive(Component)]
+struct ShareUI;
+
+#[derive(Component)]
+struct ShareInput;
+
+#[derive(Component)]
+struct ShareMessage;
+
+pub fn plugin(app: &mut App) {
+    app.add_event::<LoadPosition>()
+        .add_systems(Update, open_share.run_if(in_state(AppState::InGame)))
+        .add_systems(OnEnter(AppState::Share), share_ui)
+        .add_systems(
+            OnExit(AppState::Share),
+            (cleanup_system::<ShareUI>, close_share),
+        )
+        .add_systems(
+            Update,
+            ((share_keys, share_buttons), load_position, update_share_ui)
+                .chain()
+                .run_if(in_state(AppState::Share)),
+        );
+}
+
+/// The game that carries on from a shared position. Its recording starts
+/// from the whole board and score, and milestones already on it are not
+/// celebrated again.
+pub fn game_from_position(
+    position: SharedPosition,
+    rules: SpawnRules,
+    win_condition: &WinCondition,
+) -> SavedGame {
+    let SharedPosition { grid, score, seed } = position;
+    let grid = grid.with_rules(rules);
+    let recording = Recording::from_position(&grid, seed, score);
+    let has_won = win_condition.reached(0, grid.max_value()).unwrap_or(0);
+    SavedGame {
+        imported: true,
+        ..SavedGame::new(grid, score, has_won, GameRng::from_seed(seed), 0, recording)
+    }
+}
+
+fn open_share(input: Res<ButtonInput<KeyCode>>, mut next_state: ResMut<NextState<AppState>>) {
+    if input.just_pressed(KeyCode::KeyP) {
+        next_state.set(AppState::Share);
+    }
+}
+
+fn close_share(mut commands: Commands) {
+    commands.remove_resource::<ShareDialog>();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn copy_to_clipboard(text: &str) -> Result<(), String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn paste_from_clipboard() -> Result<String, String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|err| err.to_string())
+}
+
+// The browser only hands out the clipboard asynchronously, on a user
+// gesture; codes have to be typed there.
+#[cfg(target_arch = "wasm32")]
+fn copy_to_clipboard(_text: &str) -> Result<(), String> {
+    Err("no clipboard in the browser".into())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn paste_from_clipboard() -> Result<String, String> {
+    Err("no clipboard in the browser".into())
+}
+
+impl ShareDialog {
+    fn copy(&mut self) {
+        self.message = match copy_to_clipboard(&self.code) {
+            Ok(()) => "Copied".into(),
+            Err(err) => format!("Could not copy: {err}"),
+        };
+    }
+
+    fn paste(&mut self) {
+        match paste_from_clipboard() {
+            Ok(text) => self.input = text.trim().chars().take(MAX_CODE_LEN).collect(),
+            Err(err) => self.message = format!("Could not paste: {err}"),
+        }
+    }
+
+    /// The position typed in, if it can be played on `grid`.
+    fn read(&mut self, grid: &Grid) -> Option<SharedPosition> {
+        let position = match share::decode(&self.input) {
+            Ok(position) => position,
+            Err(err) => {
+                self.message = format!("Cannot load: {err}");
+                return None;
+            }
+        };
+        let (width, height) = (position.grid.width, position.grid.height);
+        if !fits_window(grid, width, height) {
+            self.message = format!("This is a {width}x{height} board, start with --load");
+            return None;
+        }
+        Some(position)
+    }
+}
+
+fn share_keys(
+    input: Res<ButtonInput<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut dialog: ResMut<ShareDialog>,
+    grid: Res<Grid>,
+    mut loads: EventWriter<LoadPosition>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let ctrl = input.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ]);
+    if ctrl {
+        if input.just_pressed(KeyCode::KeyC) {
+            dialog.copy();
+        }
+        if input.just_pressed(KeyCode::KeyV) {
+            dialog.paste();
+        }
+        characters.clear();
+    }
+
+    for event in characters.read() {
+        for c in event.char.chars() {
+            if (c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                && dialog.input.len() < MAX_CODE_LEN
+            {
+                dialog.input.push(c);
+            }
+        }
+    }
+    if input.just_pressed(KeyCode::Backspace) {
+        dialog.input.pop();
+    }
+
+    if input.any_just_pressed([KeyCode::Enter, KeyCode::NumpadEnter]) {
+        if dialog.input.is_empty() {
+            next_state.set(AppState::InGame);
+        } else if let Some(position) = dialog.read(&grid) {
+            loads.send(LoadPosition(position));
+        }
+    }
+}
+
+fn share_buttons(
+    mut dialog: ResMut<ShareDialog>,
+    grid: Res<Grid>,
+    mut loads: EventWriter<LoadPosition>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut interaction_query: Query<
+        (&Interaction, &ShareButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+) {
+    for (interaction, button, mut color) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                match button {
+                    ShareButton::Copy => dialog.copy(),
+                    ShareButton::Paste => dialog.paste(),
+                    ShareButton::Load => {
+                        if let Some(position) = dialog.read(&grid) {
+                            loads.send(LoadPosition(position));
+                        }
+                    }
+                    ShareButton::Close => next_state.set(AppState::InGame),
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+/// Replace the game in progress with a shared position. The tiles on
+/// screen are dropped and rebuilt by `sync_tiles`.
+#[allow(clippy::too_many_arguments)]
+fn load_position(
+    mut commands: Commands,
+    mut loads: EventReader<LoadPosition>,
+    options: Res<Options>,
+    win_condition: Res<WinCondition>,
+    mut grid: ResMut<Grid>,
+    mut rng: ResMut<GameRng>,
+    mut score: ResMut<Score>,
+    mut has_won: ResMut<HasWon>,
+    mut history: ResMut<History>,
+    mut stats: ResMut<GameStats>,
+    mut recording: ResMut<Recording>,
+    mut next_state: ResMut<NextState<AppState>>,
+    tiles: Query<Entity, With<Cell>>,
+) {
+    let Some(LoadPosition(position)) = loads.read().last().cloned() else {
+        return;
+    };
+
+    let game = game_from_position(position, options.difficulty.clone(), &win_condition);
+    info!("playing a shared position with seed {}", game.rng.seed());
+    *grid = game.grid;
+    *rng = game.rng;
+    score.current = game.score;
+    score.imported = game.imported;
+    has_won.0 = game.has_won;
+    history.clear();
+    *stats = GameStats::default();
+    *recording = game.recording;
+    for entity in &tiles {
+        commands.entity(entity).despawn_recursive();
+    }
+    next_state.set(AppState::InGame);
+}
+
+fn update_share_ui(
+    dialog: Res<ShareDialog>,
+    mut input: Query<&mut Text, (With<ShareInput>, Without<ShareMessage>)>,
+    mut message: Query<&mut Text, (With<ShareMessage>, Without<ShareInput>)>,
+) {
+    if !dialog.is_changed() {
+        return;
+    }
+    for mut text in &mut input {
+        text.sections[0].value = format!("{}_", dialog.input);
+    }
+    for mut text in &mut message {
+        text.sections[0].value.clone_from(&dialog.message);
+    }
+}
+
+fn share_ui(
+    mut commands: Commands,
+    font: Res<GameFont>,
+    grid: Res<Grid>,
+    score: Res<Score>,
+    rng: Res<GameRng>,
+    mut characters: ResMut<Events<ReceivedCharacter>>,
+) {
+    // Drop the P that opened the dialog.
+    characters.clear();
+
+    let code = share::encode(&grid, score.current, rng.seed());
+    info!("share code {code}");
+
+    let text_style = |font_size, color| TextStyle {
+        font: font.0.clone(),
+        font_size,
+        color,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                z_index: ZIndex::Global(2),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(16.0),
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(1.0, 1.0, 1.0, 0.9)),
+                ..default()
+            },
+            ShareUI,
+        ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section(
+                "Share this position",
+                text_style(42.0, Color::BLACK),
+            ));
+            builder.spawn(TextBundle::from_section(
+                code.clone(),
+                text_style(20.0, Color::hex("776e65").unwrap()),
+            ));
+            builder.spawn(TextBundle::from_section(
+                "or enter a code to play it",
+                text_style(28.0, Color::BLACK),
+            ));
+            builder.spawn((
+                TextBundle::from_section("_", text_style(20.0, Color::BLACK)),
+                ShareInput,
+            ));
+            builder.spawn((
+                TextBundle::from_section("", text_style(20.0, Color::hex("f65e3b").unwrap())),
+                ShareMessage,
+            ));
+            builder
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        column_gap: Val::Px(10.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|builder| {
+                    for (label, button) in [
+                        ("Copy", ShareButton::Copy),
+                        ("Paste", ShareButton::Paste),
+                        ("Load", ShareButton::Load),
+                        ("Close", ShareButton::Close),
+                    ] {
+                        builder
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        padding: UiRect::all(Val::Px(10.0)),
+                                        ..default()
+                                    },
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                button,
+                            ))
+                            .with_children(|builder| {
+                                builder.spawn(TextBundle::from_section(
+                                    label,
+                                    text_style(28.0, Color::WHITE),
+                                ));
+                            });
+                    }
+                });
+        });
+
+    commands.insert_resource(ShareDialog { code, ..default() });
+}
@@ -0,0 +1,343 @@
+//! Expectimax search for the best move.
+//!
+//! The player picks the move with the best expected outcome; the game then
+//! drops a tile on any empty cell with equal chance, its value weighted as
+//! in the board's [`SpawnRules`](crate::grid::SpawnRules). The tree is
+//! searched to a fixed number of moves, deepening one move at a time until
+//! the depth or the time runs out, and boards met twice at the same depth
+//! are looked up instead of searched again. Leaves are scored with the
+//! [`heuristics`](crate::heuristics).
+//!
+//! Classic 4x4 boards are searched as [`Bitboard`]s, other sizes as
+//! [`Grid`]s, which is much slower. Only one tile is spawned per chance
+//! node, whatever `per_move` says.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use crate::bitboard::Bitboard;
+use crate::grid::{value_to_rank, Direction, Grid};
+use crate::heuristics::{self, Cells, Weights};
+
+/// Value of a board with no move left, well below any heuristic score.
+const LOST: f32 = -1.0e6;
+
+/// How far and how long to search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solver {
+    /// Moves to look ahead, at least 1.
+    pub depth: u32,
+    /// Give up deepening after this long, keeping the deepest search that
+    /// finished. The first move ahead is always searched in full.
+    pub time: Option<Duration>,
+    /// Boards reached with a probability below this, the product of the
+    /// chances of every spawn on the way from the root, are scored as they
+    /// are instead of searched further.
+    pub min_probability: f32,
+    /// How leaves are scored.
+    pub weights: Weights,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self {
+            depth: 3,
+            time: Some(Duration::from_millis(100)),
+            min_probability: 1e-4,
+            weights: Weights::default(),
+        }
+    }
+}
+
+/// What a search found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /// The move to play, `None` if the game is over.
+    pub best: Option<Direction>,
+    /// Expected value of every legal move, best first.
+    pub values: Vec<(Direction, f32)>,
+    /// Moves looked ahead by the deepest search that finished.
+    pub depth: u32,
+    /// Boards evaluated or expanded, over every depth.
+    pub positions: u64,
+}
+
+impl Solver {
+    /// Search at most `depth` moves ahead, with no time limit.
+    #[must_use]
+    pub fn with_depth(depth: u32) -> Self {
+        Self {
+            depth,
+            time: None,
+            ..Self::default()
+        }
+    }
+
+    /// The move to play on `grid`, `None` if there is none.
+    #[must_use]
+    pub fn best_move(&self, grid: &Grid) -> Option<Direction> {
+        self.analyse(grid).best
+    }
+
+    #[must_use]
+    pub fn analyse(&self, grid: &Grid) -> Analysis {
+        let spawns = spawn_ranks(grid);
+        // Two 32768s would merge on a grid but not on a bitboard.
+        match Bitboard::from_grid(grid).filter(|board| board.max_rank() < 15) {
+            Some(board) => self.search(board, &spawns),
+            None => self.search(grid.clone(), &spawns),
+        }
+    }
+
+    fn search<B: Board>(&self, board: B, spawns: &[(u8, f32)]) -> Analysis {
+        let mut search = Search {
+            solver: self,
+            spawns,
+            deadline: self.time.map(|time| Instant::now() + time),
+            cache: HashMap::new(),
+            positions: 0,
+            out_of_time: false,
+            pruned: false,
+        };
+        let mut analysis = Analysis {
+            best: None,
+            values: Vec::new(),
+            depth: 0,
+            positions: 0,
+        };
+
+        for depth in 1..=self.depth.max(1) {
+            let mut values = Vec::new();
+            for direction in Direction::ALL {
+                if let Some(moved) = board.moved(direction) {
+                    values.push((direction, search.expect(&moved, depth - 1, 1.0)));
+                }
+            }
+            if search.out_of_time && depth > 1 {
+                break;
+            }
+            values.sort_by(|a, b| b.1.total_cmp(&a.1));
+            analysis.best = values.first().map(|&(direction, _)| direction);
+            analysis.values = values;
+            analysis.depth = depth;
+            // Nothing deeper to find once the game is over.
+            if analysis.best.is_none() || search.out_of_time {
+                break;
+            }
+        }
+
+        analysis.positions = search.positions;
+        analysis
+    }
+}
+
+/// Ranks the board's rules spawn, with their probabilities.
+fn spawn_ranks(grid: &Grid) -> Vec<(u8, f32)> {
+    let total: f32 = grid.rules.values.iter().map(|&(_, weight)| weight).sum();
+    grid.rules
+        .values
+        .iter()
+        .filter_map(|&(value, weight)| Some((value_to_rank(value)?, weight / total)))
+        .filter(|&(rank, probability)| rank != 0 && probability > 0.0)
+        .collect()
+}
+
+/// What the search needs from a board.
+trait Board: Cells + Sized {
+    type Key: Hash + Eq;
+
+    fn key(&self) -> Self::Key;
+    /// The board after `direction`, `None` if nothing moves.
+    fn moved(&self, direction: Direction) -> Option<Self>;
+    /// The board with `rank` placed on the empty cell `(i, j)`.
+    fn place(&self, i: usize, j: usize, rank: u8) -> Self;
+}
+
+impl Board for Bitboard {
+    type Key = u64;
+
+    fn key(&self) -> u64 {
+        self.0
+    }
+
+    fn moved(&self, direction: Direction) -> Option<Self> {
+        let (moved, _) = self.slide(direction);
+        (moved != *self).then_some(moved)
+    }
+
+    fn place(&self, i: usize, j: usize, rank: u8) -> Self {
+        Self(self.0 | u64::from(rank) << (4 * Grid::index_2d(i, j, 4, 4)))
+    }
+}
+
+impl Board for Grid {
+    type Key = Vec<u8>;
+
+    fn key(&self) -> Vec<u8> {
+        self.cells.clone()
+    }
+
+    fn moved(&self, direction: Direction) -> Option<Self> {
+        let mut moved = self.clone();
+        moved.slide(direction).changed.then_some(moved)
+    }
+
+    fn place(&self, i: usize, j: usize, rank: u8) -> Self {
+        let mut placed = self.clone();
+        placed.cells[Grid::index_2d(i, j, self.width, self.height)] = rank;
+        placed
+    }
+}
+
+struct Search<'a, B: Board> {
+    solver: &'a Solver,
+    spawns: &'a [(u8, f32)],
+    deadline: Option<Instant>,
+    /// Expected value of boards waiting for a spawn, by moves left.
+    cache: HashMap<(B::Key, u32), f32>,
+    positions: u64,
+    out_of_time: bool,
+    /// Whether an unlikely spawn was cut off below the board being valued.
+    pruned: bool,
+}
+
+impl<B: Board> Search<'_, B> {
+    /// Expected value of `board` before a tile spawns, with `depth` moves
+    /// left to look at.
+    fn expect(&mut self, board: &B, depth: u32, probability: f32) -> f32 {
+        self.positions += 1;
+        if depth == 0 {
+            return heuristics::score(board, &self.solver.weights);
+        }
+        if probability < self.solver.min_probability {
+            self.pruned = true;
+            return heuristics::score(board, &self.solver.weights);
+        }
+        let key = (board.key(), depth);
+        if let Some(&value) = self.cache.get(&key) {
+            return value;
+        }
+
+        let empty: Vec<(usize, usize)> = (0..board.height())
+            .flat_map(|j| (0..board.width()).map(move |i| (i, j)))
+            .filter(|&(i, j)| board.cell(i, j) == 0)
+            .collect();
+        if empty.is_empty() {
+            return self.best(board, depth, probability);
+        }
+
+        let pruned_before = std::mem::take(&mut self.pruned);
+        let cell_probability = 1.0 / empty.len() as f32;
+        let mut value = 0.0;
+        for &(i, j) in &empty {
+            for &(rank, spawn_probability) in self.spawns {
+                let p = cell_probability * spawn_probability;
+                value += p * self.best(&board.place(i, j, rank), depth, probability * p);
+            }
+        }
+
+        // A search cut short leaves values that are not worth keeping, and
+        // one that skipped unlikely spawns only holds for boards as unlikely.
+        if !self.out_of_time && !self.pruned {
+            self.cache.insert(key, value);
+        }
+        self.pruned |= pruned_before;
+        value
+    }
+
+    /// Value of the best move on `board`, with `depth` moves left to look at.
+    // `is_multiple_of` is too recent for the toolchains the game builds on.
+    #[allow(unknown_lints, clippy::manual_is_multiple_of)]
+    fn best(&mut self, board: &B, depth: u32, probability: f32) -> f32 {
+        self.positions += 1;
+        if self.out_of_time {
+            return LOST;
+        }
+        if self.positions % 1024 == 0 {
+            self.out_of_time = self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline);
+        }
+
+        Direction::ALL
+            .into_iter()
+            .filter_map(|direction| board.moved(direction))
+            .map(|moved| self.expect(&moved, depth - 1, probability))
+            .fold(LOST, f32::max)
+    }
+}
+
+#[cfg(test)]
+mod solver_tests {
+    use super::*;
+    use crate::rng::GameRng;
+
+    #[test]
+    fn follows_the_only_move() {
+        #[rustfmt::skip]
+        let grid = Grid::from_values(4, 4, &[
+            2, 4, 2, 4,
+            4, 2, 4, 2,
+            2, 4, 2, 4,
+            4, 2, 4, 0,
+        ]);
+        let analysis = Solver::with_depth(2).analyse(&grid);
+        assert_eq!(analysis.values.len(), 2);
+        assert!(matches!(
+            analysis.best,
+            Some(Direction::Right | Direction::Up)
+        ));
+
+        #[rustfmt::skip]
+        let lost = Grid::from_values(2, 2, &[
+            2, 4,
+            4, 2,
+        ]);
+        assert_eq!(Solver::default().best_move(&lost), None);
+    }
+
+    #[test]
+    fn bitboards_and_grids_agree() {
+        #[rustfmt::skip]
+        let grid = Grid::from_values(4, 4, &[
+            2, 2, 0, 4,
+            0, 8, 4, 0,
+            0, 0, 2, 0,
+            16, 0, 0, 2,
+        ]);
+        let solver = Solver::with_depth(2);
+        let spawns = spawn_ranks(&grid);
+        let packed = solver.search(Bitboard::from_grid(&grid).unwrap(), &spawns);
+        let slow = solver.search(grid, &spawns);
+        assert_eq!(packed, slow);
+    }
+
+    #[test]
+    fn stops_when_out_of_time() {
+        let mut rng = GameRng::from_seed(3);
+        let mut grid = Grid::with_size(5, 5);
+        grid.start(&mut rng);
+        let solver = Solver {
+            depth: 50,
+            time: Some(Duration::from_millis(20)),
+            ..Solver::default()
+        };
+        let analysis = solver.analyse(&grid);
+        assert!(analysis.best.is_some());
+        assert!(analysis.depth < 50);
+    }
+
+    #[test]
+    fn plays_a_decent_game() {
+        let mut rng = GameRng::from_seed(7);
+        let mut grid = Grid::new();
+        grid.start(&mut rng);
+        let solver = Solver::with_depth(2);
+        while grid.max_value() < 512 {
+            let direction = solver.best_move(&grid).expect("lost before 512");
+            assert!(grid.slide(direction).changed);
+            grid.spawn_tiles(&mut rng);
+        }
+    }
+}
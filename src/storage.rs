@@ -0,0 +1,515 @@
+//! Everything the game keeps between runs.
+//!
+//! Each [`Document`] is a RON text with a top-level `version` field, kept
+//! under its own key by a [`StorageBackend`]: files in the data directory on
+//! desktop, `localStorage` in the browser. Writes are skipped when the
+//! contents have not changed. A document that cannot be read is renamed to
+//! `<name>.corrupt` and reported, so the game starts afresh without losing it.
+//! A document of another version is left alone until something is stored
+//! in its place, when it is renamed to `<name>.v<version>` first.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// File the high score lived in before this module existed.
+const LEGACY_HIGHSCORE: &str = "highscore.txt";
+
+/// A value stored in a file of its own.
+pub trait Document: Serialize + DeserializeOwned {
+    const FILE: &'static str;
+    const VERSION: u32;
+
+    /// Reject values that parsed but make no sense.
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(std::io::Error),
+    /// The file could not be understood and was moved out of the way.
+    Corrupt {
+        file: String,
+        reason: String,
+    },
+    /// The file was written by another version of the game.
+    Version {
+        file: String,
+        found: u32,
+    },
+    Serialize(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Corrupt { file, reason } => write!(f, "{file} is corrupt: {reason}"),
+            Self::Version { file, found } => {
+                write!(f, "{file} has unsupported version {found}")
+            }
+            Self::Serialize(reason) => write!(f, "could not serialise: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<std::io::Error> for StorageError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Just enough of a document to check its version before reading the rest.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Scores {
+    pub version: u32,
+    pub highscore: u64,
+}
+
+impl Scores {
+    #[must_use]
+    pub fn new(highscore: u64) -> Self {
+        Self {
+            version: Self::VERSION,
+            highscore,
+        }
+    }
+}
+
+impl Default for Scores {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Document for Scores {
+    const FILE: &'static str = "scores.ron";
+    const VERSION: u32 = 1;
+}
+
+/// Where documents end up. Keys are plain file names.
+pub trait StorageBackend: Send + Sync {
+    /// The text stored under `key`, `None` if there is none.
+    fn read(&self, key: &str) -> std::io::Result<Option<String>>;
+
+    /// Replace what is stored under `key`. Readers see either the old or
+    /// the new text, never a mix.
+    fn write(&mut self, key: &str, text: &str) -> std::io::Result<()>;
+
+    /// Delete `key`, doing nothing if it does not exist.
+    fn remove(&mut self, key: &str) -> std::io::Result<()>;
+
+    /// Move what is under `from` to `to`, replacing it.
+    fn rename(&mut self, from: &str, to: &str) -> std::io::Result<()> {
+        if let Some(text) = self.read(from)? {
+            self.write(to, &text)?;
+        }
+        self.remove(from)
+    }
+}
+
+/// One file per key in a directory, created on the first write.
+pub struct FileBackend {
+    dir: PathBuf,
+}
+
+impl FileBackend {
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl StorageBackend for FileBackend {
+    fn read(&self, key: &str) -> std::io::Result<Option<String>> {
+        match std::fs::read_to_string(self.dir.join(key)) {
+            Ok(text) => Ok(Some(text)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn write(&mut self, key: &str, text: &str) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(key);
+        let temporary = self.dir.join(format!("{key}.tmp"));
+        std::fs::write(&temporary, text)?;
+        std::fs::rename(&temporary, path)
+    }
+
+    fn remove(&mut self, key: &str) -> std::io::Result<()> {
+        match std::fs::remove_file(self.dir.join(key)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> std::io::Result<()> {
+        std::fs::rename(self.dir.join(from), self.dir.join(to))
+    }
+}
+
+/// Keeps everything in memory, for tests and platforms without storage.
+#[derive(Default, Clone)]
+pub struct MemoryBackend {
+    pub entries: HashMap<String, String>,
+}
+
+impl StorageBackend for MemoryBackend {
+    fn read(&self, key: &str) -> std::io::Result<Option<String>> {
+        Ok(self.entries.get(key).cloned())
+    }
+
+    fn write(&mut self, key: &str, text: &str) -> std::io::Result<()> {
+        self.entries.insert(key.to_string(), text.to_string());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> std::io::Result<()> {
+        self.entries.remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub use local_storage::LocalStorageBackend;
+
+#[cfg(target_arch = "wasm32")]
+mod local_storage {
+    use std::io::Error;
+
+    use wasm_bindgen::JsValue;
+
+    use super::StorageBackend;
+
+    /// Prefix keeping our keys apart from anything else on the origin.
+    const PREFIX: &str = "shadowmitia_2048/";
+
+    /// The browser's `window.localStorage`.
+    pub struct LocalStorageBackend {
+        storage: web_sys::Storage,
+    }
+
+    // The wasm build is single threaded.
+    unsafe impl Send for LocalStorageBackend {}
+    unsafe impl Sync for LocalStorageBackend {}
+
+    fn js_error(err: JsValue) -> Error {
+        Error::other(format!("{err:?}"))
+    }
+
+    impl LocalStorageBackend {
+        /// `None` if the page has no `localStorage`, e.g. when it is
+        /// disabled by the browser.
+        #[must_use]
+        pub fn new() -> Option<Self> {
+            let storage = web_sys::window()?.local_storage().ok()??;
+            Some(Self { storage })
+        }
+    }
+
+    impl StorageBackend for LocalStorageBackend {
+        fn read(&self, key: &str) -> std::io::Result<Option<String>> {
+            self.storage
+                .get_item(&format!("{PREFIX}{key}"))
+                .map_err(js_error)
+        }
+
+        fn write(&mut self, key: &str, text: &str) -> std::io::Result<()> {
+            self.storage
+                .set_item(&format!("{PREFIX}{key}"), text)
+                .map_err(js_error)
+        }
+
+        fn remove(&mut self, key: &str) -> std::io::Result<()> {
+            self.storage
+                .remove_item(&format!("{PREFIX}{key}"))
+                .map_err(js_error)
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct Storage {
+    backend: Box<dyn StorageBackend>,
+    /// What each key is known to hold, to skip identical writes.
+    written: HashMap<&'static str, String>,
+}
+
+impl Storage {
+    #[must_use]
+    pub fn new(backend: impl StorageBackend + 'static) -> Self {
+        Self {
+            backend: Box::new(backend),
+            written: HashMap::new(),
+        }
+    }
+
+    /// The platform's own storage: `localStorage` in the browser, the data
+    /// directory elsewhere. Falls back to memory, keeping nothing, when
+    /// neither is available.
+    #[must_use]
+    pub fn open() -> Self {
+        #[cfg(target_arch = "wasm32")]
+        if let Some(backend) = LocalStorageBackend::new() {
+            return Self::new(backend);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(dirs) = directories::ProjectDirs::from("eu", "shadowmitia", "2048") {
+            return Self::new(FileBackend::new(dirs.data_dir()));
+        }
+
+        warn!("no persistent storage, nothing will be kept");
+        Self::new(MemoryBackend::default())
+    }
+
+    /// Read a document, `Ok(None)` if it was never stored.
+    pub fn load<T: Document>(&mut self) -> Result<Option<T>, StorageError> {
+        let Some(text) = self.backend.read(T::FILE)? else {
+            return Ok(None);
+        };
+
+        match parse::<T>(&text) {
+            Ok(value) => {
+                self.written.insert(T::FILE, text);
+                Ok(Some(value))
+            }
+            Err(Parse::Version(found)) => Err(StorageError::Version {
+                file: T::FILE.to_string(),
+                found,
+            }),
+            Err(Parse::Invalid(reason)) => Err(self.set_aside(T::FILE, reason)),
+        }
+    }
+
+    /// Write a document unless the backend already holds the same thing.
+    /// Returns whether anything was written.
+    pub fn store<T: Document>(&mut self, value: &T) -> Result<bool, StorageError> {
+        let text = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+            .map_err(|err| StorageError::Serialize(err.to_string()))?;
+        if self.written.get(T::FILE) == Some(&text) {
+            return Ok(false);
+        }
+        if !self.written.contains_key(T::FILE) {
+            self.keep_other_version::<T>()?;
+        }
+
+        self.backend.write(T::FILE, &text)?;
+        self.written.insert(T::FILE, text);
+        Ok(true)
+    }
+
+    /// The stored scores, carrying over a `highscore.txt` from older
+    /// versions the first time.
+    pub fn load_scores(&mut self) -> Result<Scores, StorageError> {
+        if let Some(scores) = self.load::<Scores>()? {
+            return Ok(scores);
+        }
+        let Some(text) = self.backend.read(LEGACY_HIGHSCORE)? else {
+            return Ok(Scores::default());
+        };
+
+        match text.trim().parse() {
+            Ok(highscore) => {
+                let scores = Scores::new(highscore);
+                self.store(&scores)?;
+                self.backend.remove(LEGACY_HIGHSCORE)?;
+                Ok(scores)
+            }
+            Err(err) => Err(self.set_aside(LEGACY_HIGHSCORE, err.to_string())),
+        }
+    }
+
+    /// Rename a document written by another version of the game, so that
+    /// storing this one does not destroy it.
+    fn keep_other_version<T: Document>(&mut self) -> Result<(), StorageError> {
+        let Some(text) = self.backend.read(T::FILE)? else {
+            return Ok(());
+        };
+        match ron::from_str::<Header>(&text) {
+            Ok(header) if header.version != T::VERSION => {
+                let kept = format!("{}.v{}", T::FILE, header.version);
+                self.backend.rename(T::FILE, &kept)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Move an unreadable document out of the way and describe the
+    /// problem.
+    fn set_aside(&mut self, file: &'static str, reason: String) -> StorageError {
+        self.written.remove(file);
+        if let Err(err) = self.backend.rename(file, &format!("{file}.corrupt")) {
+            return StorageError::Corrupt {
+                file: file.to_string(),
+                reason: format!("{reason} (and it could not be moved aside: {err})"),
+            };
+        }
+        StorageError::Corrupt {
+            file: file.to_string(),
+            reason,
+        }
+    }
+}
+
+enum Parse {
+    Version(u32),
+    Invalid(String),
+}
+
+fn parse<T: Document>(text: &str) -> Result<T, Parse> {
+    let header: Header = ron::from_str(text).map_err(|err| Parse::Invalid(err.to_string()))?;
+    if header.version != T::VERSION {
+        return Err(Parse::Version(header.version));
+    }
+    let value: T = ron::from_str(text).map_err(|err| Parse::Invalid(err.to_string()))?;
+    value.validate().map_err(Parse::Invalid)?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod storage_tests {
+    use super::*;
+
+    /// Shares its entries with the test, to look at what was stored.
+    #[derive(Clone, Default)]
+    struct SharedMemory(std::sync::Arc<std::sync::Mutex<MemoryBackend>>);
+
+    impl SharedMemory {
+        fn get(&self, key: &str) -> Option<String> {
+            self.0.lock().unwrap().entries.get(key).cloned()
+        }
+
+        fn set(&self, key: &str, text: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .entries
+                .insert(key.to_string(), text.to_string());
+        }
+    }
+
+    impl StorageBackend for SharedMemory {
+        fn read(&self, key: &str) -> std::io::Result<Option<String>> {
+            self.0.lock().unwrap().read(key)
+        }
+
+        fn write(&mut self, key: &str, text: &str) -> std::io::Result<()> {
+            self.0.lock().unwrap().write(key, text)
+        }
+
+        fn remove(&mut self, key: &str) -> std::io::Result<()> {
+            self.0.lock().unwrap().remove(key)
+        }
+    }
+
+    #[test]
+    fn stores_only_changes() {
+        let memory = SharedMemory::default();
+        let mut storage = Storage::new(memory.clone());
+
+        assert!(storage.load::<Scores>().unwrap().is_none());
+        assert!(storage.store(&Scores::new(8)).unwrap());
+        assert!(!storage.store(&Scores::new(8)).unwrap());
+        assert!(storage.store(&Scores::new(16)).unwrap());
+
+        let mut reopened = Storage::new(memory);
+        assert_eq!(reopened.load::<Scores>().unwrap().unwrap().highscore, 16);
+        assert!(!reopened.store(&Scores::new(16)).unwrap());
+    }
+
+    #[test]
+    fn file_backend_creates_the_directory() {
+        let dir = std::env::temp_dir().join(format!("shadowmitia_2048-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut storage = Storage::new(FileBackend::new(dir.join("nested")));
+
+        assert!(storage.store(&Scores::new(32)).unwrap());
+        assert!(!dir.join("nested").join("scores.ron.tmp").exists());
+        let mut reopened = Storage::new(FileBackend::new(dir.join("nested")));
+        assert_eq!(reopened.load::<Scores>().unwrap().unwrap().highscore, 32);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_documents_are_reported_and_moved_aside() {
+        let memory = SharedMemory::default();
+        memory.set(Scores::FILE, "(version: 1, highscore: \"lots\")");
+        let mut storage = Storage::new(memory.clone());
+
+        assert!(matches!(
+            storage.load::<Scores>(),
+            Err(StorageError::Corrupt { .. })
+        ));
+        assert!(memory.get("scores.ron.corrupt").is_some());
+        assert!(storage.load::<Scores>().unwrap().is_none());
+
+        memory.set(Scores::FILE, "(version: 7, highscore: 4)");
+        assert!(matches!(
+            storage.load::<Scores>(),
+            Err(StorageError::Version { found: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn documents_of_other_versions_are_kept() {
+        let newer = "(version: 7, highscore: 4)";
+        let memory = SharedMemory::default();
+        memory.set(Scores::FILE, newer);
+        let mut storage = Storage::new(memory.clone());
+
+        assert!(storage.load::<Scores>().is_err());
+        assert_eq!(memory.get(Scores::FILE).as_deref(), Some(newer));
+        assert!(storage.store(&Scores::new(8)).unwrap());
+        assert_eq!(memory.get("scores.ron.v7").as_deref(), Some(newer));
+        assert_eq!(storage.load::<Scores>().unwrap().unwrap().highscore, 8);
+
+        // Even when nothing was loaded first.
+        memory.set(Scores::FILE, newer);
+        let mut unaware = Storage::new(memory.clone());
+        assert!(unaware.store(&Scores::new(16)).unwrap());
+        assert_eq!(memory.get("scores.ron.v7").as_deref(), Some(newer));
+        assert!(unaware.store(&Scores::new(32)).unwrap());
+    }
+
+    #[test]
+    fn migrates_legacy_highscore() {
+        let memory = SharedMemory::default();
+        memory.set(LEGACY_HIGHSCORE, "20480\n");
+        let mut storage = Storage::new(memory.clone());
+
+        assert_eq!(storage.load_scores().unwrap().highscore, 20480);
+        assert!(memory.get(LEGACY_HIGHSCORE).is_none());
+        assert_eq!(
+            Storage::new(memory.clone())
+                .load::<Scores>()
+                .unwrap()
+                .unwrap()
+                .highscore,
+            20480
+        );
+
+        let memory = SharedMemory::default();
+        memory.set(LEGACY_HIGHSCORE, "garbage");
+        let mut storage = Storage::new(memory);
+        assert!(storage.load_scores().is_err());
+        assert_eq!(storage.load_scores().unwrap().highscore, 0);
+    }
+}
@@ -0,0 +1,188 @@
+//! Ways for the game to pick its own moves.
+//!
+//! A [`Strategy`] looks at the board and answers a legal move, or `None`
+//! once the game is lost. [`StrategyKind`] names the built-in ones so they
+//! can be chosen on the command line or cycled through in game.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rand::seq::SliceRandom;
+
+use crate::grid::{Direction, Grid};
+use crate::heuristics;
+use crate::rng::GameRng;
+use crate::solver::Solver;
+
+pub trait Strategy: Send {
+    /// The move to play on `grid`, `None` if nothing moves.
+    fn choose(&mut self, grid: &Grid) -> Option<Direction>;
+}
+
+/// The directions that change `grid`, with the board each leaves and the
+/// points it scores.
+fn legal_moves(grid: &Grid) -> impl DoubleEndedIterator<Item = (Direction, Grid, u64)> + '_ {
+    Direction::ALL.into_iter().filter_map(|direction| {
+        let mut moved = grid.clone();
+        let outcome = moved.slide(direction);
+        outcome.changed.then_some((direction, moved, outcome.score))
+    })
+}
+
+/// Any legal move.
+pub struct RandomMoves {
+    pub rng: GameRng,
+}
+
+impl Strategy for RandomMoves {
+    fn choose(&mut self, grid: &Grid) -> Option<Direction> {
+        let legal: Vec<Direction> = legal_moves(grid).map(|(direction, ..)| direction).collect();
+        legal.choose(&mut self.rng).copied()
+    }
+}
+
+/// The move scoring the most points right away, then the one leaving the
+/// most room.
+pub struct Greedy;
+
+impl Strategy for Greedy {
+    fn choose(&mut self, grid: &Grid) -> Option<Direction> {
+        // Ties go to the first direction.
+        legal_moves(grid)
+            .rev()
+            .max_by_key(|(_, moved, score)| (*score, heuristics::empty_cells(moved)))
+            .map(|(direction, ..)| direction)
+    }
+}
+
+/// Keep the tiles piled into the bottom left corner: down if possible, else
+/// left, else right, and up only when nothing else moves.
+pub struct Corner;
+
+impl Strategy for Corner {
+    fn choose(&mut self, grid: &Grid) -> Option<Direction> {
+        const PREFERENCE: [Direction; 4] = [
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+            Direction::Up,
+        ];
+        PREFERENCE
+            .into_iter()
+            .find(|&direction| grid.clone().slide(direction).changed)
+    }
+}
+
+impl Strategy for Solver {
+    fn choose(&mut self, grid: &Grid) -> Option<Direction> {
+        self.best_move(grid)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StrategyKind {
+    Random,
+    Greedy,
+    Corner,
+    #[default]
+    Solver,
+}
+
+impl StrategyKind {
+    pub const ALL: [StrategyKind; 4] = [Self::Random, Self::Greedy, Self::Corner, Self::Solver];
+
+    /// The kind after this one, wrapping around.
+    #[must_use]
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&kind| kind == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    #[must_use]
+    pub fn build(self) -> Box<dyn Strategy> {
+        match self {
+            Self::Random => Box::new(RandomMoves {
+                rng: GameRng::from_entropy(),
+            }),
+            Self::Greedy => Box::new(Greedy),
+            Self::Corner => Box::new(Corner),
+            Self::Solver => Box::new(Solver::default()),
+        }
+    }
+}
+
+impl fmt::Display for StrategyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Random => "random",
+            Self::Greedy => "greedy",
+            Self::Corner => "corner",
+            Self::Solver => "solver",
+        })
+    }
+}
+
+impl FromStr for StrategyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.to_string() == s)
+            .ok_or_else(|| format!("unknown strategy '{s}'"))
+    }
+}
+
+#[cfg(test)]
+mod strategy_tests {
+    use super::*;
+
+    #[test]
+    fn every_strategy_plays_legal_moves_until_stuck() {
+        for kind in StrategyKind::ALL {
+            let mut strategy = kind.build();
+            let mut rng = GameRng::from_seed(2);
+            let mut grid = Grid::with_size(3, 3);
+            grid.start(&mut rng);
+            for _ in 0..20 {
+                let Some(direction) = strategy.choose(&grid) else {
+                    assert!(!grid.has_legal_move(), "{kind} gave up early");
+                    break;
+                };
+                assert!(grid.slide(direction).changed, "{kind} played {direction:?}");
+                grid.spawn_tiles(&mut rng);
+            }
+        }
+    }
+
+    #[test]
+    fn greedy_takes_the_points_and_corner_goes_down() {
+        #[rustfmt::skip]
+        let grid = Grid::from_values(3, 3, &[
+            0, 0, 2,
+            0, 0, 4,
+            8, 8, 2,
+        ]);
+        assert!(matches!(
+            Greedy.choose(&grid),
+            Some(Direction::Left | Direction::Right)
+        ));
+        assert_eq!(Corner.choose(&grid), Some(Direction::Down));
+
+        #[rustfmt::skip]
+        let settled = Grid::from_values(2, 2, &[
+            2, 0,
+            4, 0,
+        ]);
+        assert_eq!(Corner.choose(&settled), Some(Direction::Right));
+    }
+
+    #[test]
+    fn kinds_parse_and_cycle() {
+        for kind in StrategyKind::ALL {
+            assert_eq!(kind.to_string().parse::<StrategyKind>(), Ok(kind));
+        }
+        assert!("clever".parse::<StrategyKind>().is_err());
+        assert_eq!(StrategyKind::Solver.next(), StrategyKind::Random);
+    }
+}
@@ -23,6 +23,10 @@ pub struct TweenTranslation {
     pub _starttime: Option<f32>,
 }
 
+/// Despawn the entity, children included, once its translation tween ends.
+#[derive(Component)]
+pub struct DespawnAfterTween;
+
 #[derive(Component)]
 pub struct Cell {
     pub coord: UVec2,